@@ -0,0 +1,315 @@
+use actix_web::{FromRequest, HttpRequest, dev::Payload};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    future::{Ready, ready},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Arc,
+        mpsc::{Sender, channel},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// The kind of an audit event
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Login,
+    Consent,
+    CodeIssued,
+    TokenIssued,
+    Refresh,
+    Revocation,
+    Logout,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+/// A structured audit event
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub timestamp: DateTime<Utc>,
+    pub event: EventKind,
+    pub outcome: Outcome,
+    pub issuer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<String>,
+    pub request_id: String,
+    /// The OAuth error code, in case of a failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(event: EventKind, issuer: impl Into<String>, request_id: &RequestId) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            event,
+            outcome: Outcome::Success,
+            issuer: issuer.into(),
+            client_id: None,
+            subject: None,
+            scopes: None,
+            request_id: request_id.0.clone(),
+            error: None,
+        }
+    }
+
+    pub fn client_id(mut self, client_id: Option<impl Into<String>>) -> Self {
+        self.client_id = client_id.map(Into::into);
+        self
+    }
+
+    pub fn subject(mut self, subject: Option<impl Into<String>>) -> Self {
+        self.subject = subject.map(Into::into);
+        self
+    }
+
+    pub fn scopes(mut self, scopes: Option<impl Into<String>>) -> Self {
+        self.scopes = scopes.map(Into::into);
+        self
+    }
+
+    /// Mark the event as failed, with an OAuth error code
+    pub fn failure(mut self, error: impl Into<String>) -> Self {
+        self.outcome = Outcome::Failure;
+        self.error = Some(error.into());
+        self
+    }
+}
+
+/// Writes audit events as JSON lines
+///
+/// The events are written by a dedicated thread, so that recording an event doesn't block the
+/// request on I/O.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    sender: Option<Sender<AuditEvent>>,
+    writer: Option<Arc<JoinHandle<()>>>,
+}
+
+impl AuditLog {
+    /// An audit log which drops all events
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn new(mut writer: impl Write + Send + 'static) -> Self {
+        let (sender, receiver) = channel::<AuditEvent>();
+
+        let spawned = thread::Builder::new()
+            .name("audit-log".into())
+            .spawn(move || {
+                // ends once all senders are dropped
+                for event in receiver {
+                    if let Err(err) = write_event(&mut writer, &event) {
+                        log::warn!("Failed to write audit event: {err}");
+                    }
+                }
+            });
+
+        match spawned {
+            Ok(writer) => Self {
+                sender: Some(sender),
+                writer: Some(Arc::new(writer)),
+            },
+            Err(err) => {
+                log::error!("Failed to start the audit log writer, dropping all events: {err}");
+                Self::disabled()
+            }
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    /// Append to a file, creating it if necessary
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        ))
+    }
+
+    /// If events are still written, which is also the case for a disabled log
+    ///
+    /// The writer only stops early if it panicked, after which all events are dropped.
+    pub fn is_running(&self) -> bool {
+        self.writer
+            .as_ref()
+            .is_none_or(|writer| !writer.is_finished())
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        if sender.send(event).is_err() {
+            log::warn!("Failed to record audit event: the writer has stopped");
+        }
+    }
+}
+
+fn write_event(writer: &mut impl Write, event: &AuditEvent) -> io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()
+}
+
+/// The ID of a request, taken from the `X-Request-ID` header or generated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req
+            .headers()
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        ready(Ok(Self(id)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        issuer::{Client, Issuer, RedirectUrl},
+        server::app::Application,
+    };
+    use actix_web::{App, http::header, test};
+    use serde_json::Value;
+    use std::{collections::HashMap, sync::mpsc, time::Duration};
+    use url::Url;
+
+    /// Passes each written line on
+    struct Lines(mpsc::Sender<Vec<u8>>);
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.send(buf.to_vec()).map_err(io::Error::other)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn events() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            })
+            .add_client(Client::Confidential {
+                id: "backend".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let mut app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let (sender, receiver) = mpsc::channel();
+        app.audit(AuditLog::new(Lines(sender)));
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let auth =
+            "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&response_type=code";
+
+        // there is no session to use without prompting
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&prompt=none"))
+            .append_header(("X-Request-ID", "no-session"))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri(auth).to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "client_credentials"),
+                ("client_id", "backend"),
+                ("client_secret", "wrong"),
+            ])
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let mut events = vec![];
+        while let Ok(line) = receiver.recv_timeout(Duration::from_millis(500)) {
+            events.push(serde_json::from_slice::<Value>(&line)?);
+        }
+        let summary = events
+            .iter()
+            .map(|event| {
+                (
+                    event["event"].as_str().unwrap_or_default(),
+                    event["clientId"].as_str().unwrap_or_default(),
+                    event["error"].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("login", "frontend", Some("login_required")),
+                ("login", "frontend", None),
+                ("code_issued", "frontend", None),
+                ("token_issued", "frontend", None),
+                ("token_issued", "backend", Some("invalid_client")),
+            ]
+        );
+        assert_eq!(events[0]["outcome"], "failure");
+        assert_eq!(events[0]["requestId"], "no-session");
+        assert_eq!(events[1]["outcome"], "success");
+        assert_eq!(events[1]["subject"], "Marvin");
+        assert_eq!(events[3]["subject"], "Marvin");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result, bail};
+use garage_door::config::Configuration;
+use std::path::{Path, PathBuf};
+
+/// Format of a configuration file, detected from its extension
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Parse the content, reporting the paths of all fields which are not part of the schema
+    pub fn parse(&self, content: &str, mut unknown: impl FnMut(String)) -> Result<Configuration> {
+        let callback = |path: serde_ignored::Path| {
+            let path = path.to_string();
+            // allow referencing the schema
+            if path != "$schema" {
+                unknown(path);
+            }
+        };
+
+        Ok(match self {
+            Self::Yaml => {
+                serde_ignored::deserialize(serde_yaml::Deserializer::from_str(content), callback)?
+            }
+            Self::Json => serde_ignored::deserialize(
+                &mut serde_json::Deserializer::from_str(content),
+                callback,
+            )?,
+            Self::Toml => serde_ignored::deserialize(toml::Deserializer::new(content), callback)?,
+        })
+    }
+}
+
+/// A single configuration file
+pub struct Source {
+    pub path: PathBuf,
+    pub format: Format,
+    pub content: String,
+}
+
+impl Source {
+    pub fn read(path: PathBuf, format: Format) -> Result<Self> {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read: {}", path.display()))?;
+
+        Ok(Self {
+            path,
+            format,
+            content,
+        })
+    }
+
+    pub fn parse(&self, unknown: impl FnMut(String)) -> Result<Configuration> {
+        self.format
+            .parse(&self.content, unknown)
+            .with_context(|| format!("failed to parse: {}", self.path.display()))
+    }
+}
+
+/// Collect all configuration sources
+///
+/// If the path is a directory, all files with a known extension are used, ordered by name. A
+/// single file without a known extension is treated as YAML.
+pub fn sources(path: &Path) -> Result<Vec<Source>> {
+    if !path.is_dir() {
+        let format = Format::from_path(path).unwrap_or(Format::Yaml);
+        return Ok(vec![Source::read(path.to_path_buf(), format)?]);
+    }
+
+    let mut paths = std::fs::read_dir(path)
+        .with_context(|| format!("failed to read directory: {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    let mut result = vec![];
+    for path in paths {
+        if !path.is_file() {
+            continue;
+        }
+        let Some(format) = Format::from_path(&path) else {
+            tracing::debug!("Skipping unknown file: {}", path.display());
+            continue;
+        };
+        result.push(Source::read(path, format)?);
+    }
+
+    if result.is_empty() {
+        bail!("no configuration files found in: {}", path.display());
+    }
+
+    Ok(result)
+}
+
+/// Load and merge the configuration from a file or directory
+pub fn load(path: &Path) -> Result<Configuration> {
+    let mut config = Configuration::default();
+
+    for source in sources(path)? {
+        let parsed = source.parse(|path| {
+            tracing::warn!("{}: unknown field: {path}", source.path.display());
+        })?;
+        config
+            .merge(parsed)
+            .with_context(|| format!("failed to merge: {}", source.path.display()))?;
+    }
+
+    Ok(config)
+}
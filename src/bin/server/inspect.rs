@@ -0,0 +1,298 @@
+use crate::config;
+use anyhow::{Context, Result, anyhow, bail};
+use biscuit::{ClaimsSet, Empty, SingleOrMultiple, jwk::JWKSet, jws::Compact};
+use chrono::Utc;
+use garage_door::config::Configuration;
+use serde_json::{Map, Value};
+use std::{io::Read, path::Path, process::ExitCode};
+use url::Url;
+
+type Token = Compact<ClaimsSet<Map<String, Value>>, Empty>;
+
+/// Decode a token and verify it against an issuer
+#[derive(Clone, Debug, clap::Args)]
+pub struct Inspect {
+    /// The token to inspect, use `-` to read from stdin
+    pub token: String,
+
+    /// Verify using the keys of a configured issuer
+    #[arg(short, long, conflicts_with = "jwks_url")]
+    pub issuer: Option<String>,
+
+    /// Verify using the key set of a running instance (e.g. http://localhost:8080/issuer/keys)
+    #[arg(short, long)]
+    pub jwks_url: Option<Url>,
+
+    /// Base URL the configured issuer is served from
+    #[arg(short = 'u', long, default_value = "http://localhost:8080")]
+    pub base_url: Url,
+
+    /// Expected issuer, defaults to the URL of the issuer used for verification
+    #[arg(long)]
+    pub expected_issuer: Option<String>,
+
+    /// Expected audience
+    #[arg(short, long)]
+    pub audience: Option<String>,
+}
+
+impl Inspect {
+    pub async fn run(self, path: &Path) -> Result<ExitCode> {
+        let token = match self.token.as_str() {
+            "-" => {
+                let mut token = String::new();
+                std::io::stdin().read_to_string(&mut token)?;
+                token.trim().to_string()
+            }
+            token => token.to_string(),
+        };
+
+        Ok(if self.inspect(&token, || config::load(path)).await? {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+
+    /// Print the token and the results of checking it, returning if all checks passed
+    async fn inspect(
+        &self,
+        token: &str,
+        config: impl FnOnce() -> Result<Configuration>,
+    ) -> Result<bool> {
+        let token = Token::new_encoded(token);
+
+        let header = token
+            .unverified_header()
+            .context("failed to decode header")?;
+        let claims = token
+            .unverified_payload()
+            .context("failed to decode claims")?;
+
+        println!("Header:");
+        println!("{}", serde_json::to_string_pretty(&header)?);
+        println!("Claims:");
+        println!("{}", serde_json::to_string_pretty(&claims)?);
+        println!();
+
+        let mut failed = false;
+        let mut check = |name: &str, result: Result<String, String>| match result {
+            Ok(msg) => println!("{name}: {msg}"),
+            Err(msg) => {
+                failed = true;
+                println!("{name}: FAILED: {msg}");
+            }
+        };
+
+        let (jwks, issuer) = self.key_set(config).await?;
+
+        match &jwks {
+            // the published key set doesn't carry key IDs, so we need to try all keys
+            Some(jwks) => check(
+                "Signature",
+                token
+                    .decode_with_jwks_ignore_kid(jwks)
+                    .map(|_| "valid".into())
+                    .map_err(|err| err.to_string()),
+            ),
+            None => println!("Signature: not verified"),
+        }
+
+        let expected_issuer = self.expected_issuer.clone().or(issuer);
+        let actual_issuer = claims.registered.issuer.as_deref();
+        match (&expected_issuer, actual_issuer) {
+            (_, None) => check("Issuer", Err("missing".into())),
+            (None, Some(actual)) => check("Issuer", Ok(format!("{actual} (not verified)"))),
+            (Some(expected), Some(actual)) if expected == actual => {
+                check("Issuer", Ok(actual.to_string()))
+            }
+            (Some(expected), Some(actual)) => check(
+                "Issuer",
+                Err(format!("expected '{expected}', found '{actual}'")),
+            ),
+        }
+
+        let audience = match &claims.registered.audience {
+            None => vec![],
+            Some(SingleOrMultiple::Single(aud)) => vec![aud.clone()],
+            Some(SingleOrMultiple::Multiple(aud)) => aud.clone(),
+        };
+        match &self.audience {
+            Some(expected) if audience.contains(expected) => {
+                check("Audience", Ok(audience.join(", ")))
+            }
+            Some(expected) => check(
+                "Audience",
+                Err(format!(
+                    "expected '{expected}', found [{}]",
+                    audience.join(", ")
+                )),
+            ),
+            None => check(
+                "Audience",
+                Ok(format!("{} (not verified)", audience.join(", "))),
+            ),
+        }
+
+        match claims.registered.expiry {
+            None => check("Expiry", Ok("never".into())),
+            Some(expiry) => {
+                let remaining = *expiry - Utc::now();
+                if remaining.num_seconds() > 0 {
+                    check(
+                        "Expiry",
+                        Ok(format!("{} (in {}s)", *expiry, remaining.num_seconds())),
+                    )
+                } else {
+                    check(
+                        "Expiry",
+                        Err(format!(
+                            "expired {} ({}s ago)",
+                            *expiry,
+                            -remaining.num_seconds()
+                        )),
+                    )
+                }
+            }
+        }
+
+        Ok(!failed)
+    }
+
+    /// Get the key set to verify with, and the issuer URL it belongs to
+    async fn key_set(
+        &self,
+        config: impl FnOnce() -> Result<Configuration>,
+    ) -> Result<(Option<JWKSet<Empty>>, Option<String>)> {
+        if let Some(name) = &self.issuer {
+            let mut config = config()?;
+            let issuer = config
+                .issuers
+                .remove(name)
+                .ok_or_else(|| anyhow!("unknown issuer: {name}"))?;
+
+            let mut url = self.base_url.clone();
+            url.path_segments_mut()
+                .map_err(|()| anyhow!("base URL cannot be a base: {}", self.base_url))?
+                .pop_if_empty()
+                .push(name);
+
+            let state = issuer
+                .build(url.clone())
+                .with_context(|| format!("failed to build issuer: {name}"))?;
+            let jwks = serde_json::from_value(serde_json::to_value(state.keys()?)?)?;
+
+            return Ok((Some(jwks), Some(url.to_string())));
+        }
+
+        if let Some(url) = &self.jwks_url {
+            let response = reqwest::get(url.clone())
+                .await
+                .with_context(|| format!("failed to fetch key set: {url}"))?;
+            if !response.status().is_success() {
+                bail!("failed to fetch key set: {url}: {}", response.status());
+            }
+            let jwks = response.json().await.context("failed to parse key set")?;
+
+            let issuer = url.as_str().strip_suffix("/keys").map(ToString::to_string);
+
+            return Ok((Some(jwks), issuer));
+        }
+
+        Ok((None, None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use clap::Parser;
+    use garage_door::issuer::{Issuer, JwtIdGenerator, TokenInformation};
+    use openidconnect::IssuerUrl;
+    use std::collections::HashMap;
+
+    #[derive(Debug, clap::Parser)]
+    struct Cli {
+        #[command(flatten)]
+        inspect: Inspect,
+    }
+
+    const KEY: &str = "this-is-not-a-secret-test-key-0123456789";
+
+    fn config() -> Result<Configuration> {
+        Ok(Configuration {
+            issuers: HashMap::from([("test".to_string(), Issuer::new(KEY, ["openid"])?)]),
+        })
+    }
+
+    /// Mint an ID token for the client `frontend`
+    fn token(key: &str, issuer: &str, expires_in: Duration) -> Result<String> {
+        let state = Issuer::new(key, ["openid"])?.build(issuer.parse()?)?;
+        JwtIdGenerator::new(state.key, IssuerUrl::new(issuer.into())?).mint(&TokenInformation {
+            subject: "Marvin".into(),
+            client_id: Some("frontend".into()),
+            audience: vec!["frontend".into()],
+            scope: "openid".into(),
+            expiry: Utc::now() + expires_in,
+            auth_time: None,
+            additional: Default::default(),
+            confirmation: None,
+        })
+    }
+
+    async fn inspect(token: &str, args: &[&str]) -> Result<bool> {
+        let cli = Cli::try_parse_from([&["inspect", token, "--issuer", "test"], args].concat())?;
+        cli.inspect.inspect(token, config).await
+    }
+
+    #[actix_web::test]
+    async fn inspect_token() -> Result<()> {
+        let issuer = "http://localhost:8080/test";
+        let valid = token(KEY, issuer, Duration::minutes(5))?;
+        assert!(inspect(&valid, &[]).await?);
+        assert!(inspect(&valid, &["--audience", "frontend"]).await?);
+
+        // signed with a different key
+        let forged = token(
+            "this-is-not-the-key-of-the-issuer-0123456789",
+            issuer,
+            Duration::minutes(5),
+        )?;
+        assert!(!inspect(&forged, &[]).await?);
+
+        // for a different issuer or audience
+        let other = token(KEY, "http://localhost:8080/other", Duration::minutes(5))?;
+        assert!(!inspect(&other, &[]).await?);
+        assert!(
+            !inspect(
+                &valid,
+                &["--expected-issuer", "http://localhost:8080/other"]
+            )
+            .await?
+        );
+        assert!(!inspect(&valid, &["--audience", "backend"]).await?);
+
+        let expired = token(KEY, issuer, Duration::minutes(-5))?;
+        assert!(!inspect(&expired, &[]).await?);
+
+        // unknown issuers are an error, not a failed check
+        let cli = Cli::try_parse_from(["inspect", &valid, "--issuer", "unknown"])?;
+        assert!(cli.inspect.inspect(&valid, config).await.is_err());
+
+        // the key set comes either from the configuration or from a running instance
+        assert!(
+            Cli::try_parse_from([
+                "inspect",
+                &valid,
+                "--issuer",
+                "test",
+                "--jwks-url",
+                "http://localhost:8080/test/keys",
+            ])
+            .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+mod config;
+mod inspect;
+mod mint;
+mod validate;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use garage_door::{
+    audit::AuditLog,
+    server::{Server, tls::TlsConfig},
+};
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct Cli {
+    /// Port to bind to
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+    /// Address to bind to
+    #[arg(short, long, default_value_t = Ipv6Addr::LOCALHOST.into())]
+    pub bind: IpAddr,
+    /// Base URL
+    #[arg(short = 'B', long)]
+    pub base: Option<String>,
+    /// Serve using TLS, with the PEM encoded certificate chain
+    #[arg(long, requires = "tls_key")]
+    pub tls_certificate: Option<PathBuf>,
+    /// PEM encoded private key of the TLS certificate
+    #[arg(long, requires = "tls_certificate")]
+    pub tls_key: Option<PathBuf>,
+    /// PEM encoded CA certificates, trusted to issue client certificates
+    #[arg(long, requires = "tls_certificate")]
+    pub tls_client_ca: Option<PathBuf>,
+    /// Write audit events as JSON lines to a file, use `-` for stdout
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    /// Configuration file or directory (YAML, JSON or TOML)
+    #[arg(short, long, global = true, default_value = "garage-door.yaml")]
+    pub config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Command {
+    Validate(validate::Validate),
+    Mint(Box<mint::Mint>),
+    Inspect(Box<inspect::Inspect>),
+}
+
+fn init_log() -> Result<()> {
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(true)
+                .with_target(true)
+                .with_level(true)
+                .compact(),
+        )
+        .try_init()
+        .context("error initializing logging")?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    init_log()?;
+
+    match cli.command {
+        Some(Command::Validate(command)) => command.run(&cli.config),
+        Some(Command::Mint(command)) => command.run(&cli.config),
+        Some(Command::Inspect(command)) => command.run(&cli.config).await,
+        None => run(cli).await,
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
+    tracing::info!("Starting up...");
+
+    let config = config::load(&cli.config)?;
+
+    tracing::debug!("Read configuration");
+
+    let mut server = Server::new();
+    server.port(cli.port).bind(cli.bind);
+
+    if let Some(base) = &cli.base {
+        server.base(base);
+    }
+
+    if let (Some(certificate), Some(key)) = (&cli.tls_certificate, &cli.tls_key) {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).with_context(|| format!("failed to read: {}", path.display()))
+        };
+        let mut tls = TlsConfig::from_pem(&read(certificate)?, &read(key)?)?;
+        if let Some(ca) = &cli.tls_client_ca {
+            tls = tls.client_ca_pem(&read(ca)?)?;
+        }
+        server.tls(tls);
+    }
+
+    match &cli.audit_log {
+        Some(path) if path.as_os_str() == "-" => {
+            server.audit(AuditLog::stdout());
+        }
+        Some(path) => {
+            server.audit(
+                AuditLog::file(path)
+                    .with_context(|| format!("failed to open audit log: {}", path.display()))?,
+            );
+        }
+        None => {}
+    }
+
+    for (name, issuer) in config.issuers {
+        server.add_issuer(name, issuer)?;
+    }
+
+    server.run().await?;
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,203 @@
+use crate::config;
+use anyhow::{Context, Result, anyhow};
+use chrono::{Duration, Utc};
+use garage_door::{
+    config::Configuration,
+    issuer::{
+        DEFAULT_AUDIENCE, JwtAccessGenerator, JwtIdGenerator, TokenInformation, is_reserved_claim,
+    },
+};
+use openidconnect::IssuerUrl;
+use serde_json::{Map, Value, json};
+use std::{path::Path, process::ExitCode};
+use url::Url;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TokenType {
+    /// Only the access token
+    Access,
+    /// Only the ID token
+    Id,
+    /// Both tokens, as JSON object
+    Both,
+}
+
+/// Create a signed token, without running the server
+#[derive(Clone, Debug, clap::Args)]
+pub struct Mint {
+    /// Name of the issuer
+    pub issuer: String,
+
+    /// Base URL the issuer would be served from
+    #[arg(short = 'u', long, default_value = "http://localhost:8080")]
+    pub base_url: Url,
+
+    /// The type of token to create
+    #[arg(short, long, value_enum, default_value_t = TokenType::Both)]
+    pub token: TokenType,
+
+    /// Subject of the token
+    #[arg(short, long, default_value = "Marvin")]
+    pub subject: String,
+
+    /// Client ID the token is issued to
+    #[arg(long)]
+    pub client_id: Option<String>,
+
+    /// Audience of the token, may be repeated
+    #[arg(short, long, default_values_t = [DEFAULT_AUDIENCE.to_string()])]
+    pub audience: Vec<String>,
+
+    /// Scopes (space separated)
+    #[arg(long, default_value = "openid")]
+    pub scope: String,
+
+    /// Lifetime of the token, in seconds
+    #[arg(short, long, default_value_t = 600)]
+    pub expires_in: i64,
+
+    /// Additional claim, in the form of `name=value`, may be repeated
+    ///
+    /// The value is parsed as JSON, falling back to a plain string. Registered claims, like `sub`
+    /// or `exp`, can't be set this way.
+    #[arg(long = "claim", value_parser = parse_claim)]
+    pub claims: Vec<(String, Value)>,
+}
+
+fn parse_claim(s: &str) -> Result<(String, Value), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("claim must be in the form of 'name=value': {s}"))?;
+    if is_reserved_claim(name) {
+        return Err(format!("claim '{name}' is set by the issuer"));
+    }
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Ok((name.to_string(), value))
+}
+
+impl Mint {
+    pub fn run(self, path: &Path) -> Result<ExitCode> {
+        let config = config::load(path)?;
+        println!("{}", self.mint(config)?);
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Create the requested tokens, in the form they are printed
+    fn mint(self, mut config: Configuration) -> Result<String> {
+        let issuer = config
+            .issuers
+            .remove(&self.issuer)
+            .ok_or_else(|| anyhow!("unknown issuer: {}", self.issuer))?;
+
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("base URL cannot be a base: {}", self.base_url))?
+            .pop_if_empty()
+            .push(&self.issuer);
+
+        let state = issuer
+            .build(url.clone())
+            .with_context(|| format!("failed to build issuer: {}", self.issuer))?;
+
+        let token = TokenInformation {
+            subject: self.subject,
+            client_id: self.client_id,
+            audience: self.audience,
+            scope: self.scope,
+            expiry: Utc::now() + Duration::seconds(self.expires_in),
+            auth_time: None,
+            additional: self.claims.into_iter().collect::<Map<_, _>>(),
+            confirmation: None,
+        };
+
+        let access_token = || {
+            JwtAccessGenerator::new(url.path().into(), state.key.clone()).mint(url.as_str(), &token)
+        };
+        let id_token = || {
+            JwtIdGenerator::new(state.key.clone(), IssuerUrl::from_url(url.clone())).mint(&token)
+        };
+
+        Ok(match self.token {
+            TokenType::Access => access_token()?,
+            TokenType::Id => id_token()?,
+            TokenType::Both => format!(
+                "{:#}",
+                json!({
+                    "access_token": access_token()?,
+                    "id_token": id_token()?,
+                    "token_type": "bearer",
+                    "expires_in": self.expires_in,
+                })
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::prelude::{BASE64_URL_SAFE_NO_PAD, Engine};
+    use clap::Parser;
+    use garage_door::issuer::Issuer;
+    use std::collections::HashMap;
+
+    #[derive(Debug, clap::Parser)]
+    struct Cli {
+        #[command(flatten)]
+        mint: Mint,
+    }
+
+    fn payload(token: &str) -> Result<Value> {
+        let payload = token.split('.').nth(1).unwrap_or_default();
+        Ok(serde_json::from_slice(
+            &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+        )?)
+    }
+
+    #[test]
+    fn mint() -> Result<()> {
+        let config = || -> Result<Configuration> {
+            Ok(Configuration {
+                issuers: HashMap::from([(
+                    "test".to_string(),
+                    Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?,
+                )]),
+            })
+        };
+
+        let cli = Cli::try_parse_from([
+            "mint",
+            "test",
+            "--subject",
+            "Arthur",
+            "--claim",
+            "roles=[\"admin\"]",
+            "--claim",
+            "tenant=acme",
+        ])?;
+        let tokens: Value = serde_json::from_str(&cli.mint.mint(config()?)?)?;
+        assert_eq!(tokens["token_type"], "bearer");
+        for token in [&tokens["access_token"], &tokens["id_token"]] {
+            let claims = payload(token.as_str().unwrap_or_default())?;
+            assert_eq!(claims["iss"], "http://localhost:8080/test");
+            assert_eq!(claims["sub"], "Arthur");
+            assert_eq!(claims["roles"], json!(["admin"]));
+            assert_eq!(claims["tenant"], "acme");
+        }
+
+        let cli = Cli::try_parse_from(["mint", "test", "--token", "access"])?;
+        let claims = payload(&cli.mint.mint(config()?)?)?;
+        assert_eq!(claims["aud"], DEFAULT_AUDIENCE);
+
+        let cli = Cli::try_parse_from(["mint", "unknown"])?;
+        assert!(cli.mint.mint(config()?).is_err());
+
+        // registered claims must not be overridden
+        for claim in ["sub=Arthur", "exp=0", "cnf={}"] {
+            assert!(Cli::try_parse_from(["mint", "test", "--claim", claim]).is_err());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config;
+use anyhow::Result;
+use std::{collections::HashMap, path::Path, process::ExitCode};
+
+/// Validate the configuration, without running the server
+#[derive(Clone, Debug, clap::Args)]
+pub struct Validate {}
+
+impl Validate {
+    pub fn run(self, path: &Path) -> Result<ExitCode> {
+        let mut problems = 0usize;
+        let mut report = |message: String| {
+            problems += 1;
+            println!("{message}");
+        };
+
+        let mut issuers = HashMap::new();
+
+        for source in config::sources(path)? {
+            let file = source.path.display();
+
+            let mut unknown = vec![];
+            let config = match source
+                .format
+                .parse(&source.content, |path| unknown.push(path))
+            {
+                Ok(config) => config,
+                Err(err) => {
+                    report(format!("{file}: {err}"));
+                    continue;
+                }
+            };
+
+            for path in unknown {
+                report(format!("{file}: {path}: unknown field"));
+            }
+
+            let mut names = config.issuers.keys().collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                if let Some(other) = issuers.insert(name.clone(), source.path.clone()) {
+                    report(format!(
+                        "{file}: issuers.{name}: duplicate issuer, already defined in: {}",
+                        other.display()
+                    ));
+                }
+            }
+
+            for problem in config.validate() {
+                report(format!("{file}: {problem}"));
+            }
+        }
+
+        if problems > 0 {
+            println!("Found {problems} problem(s)");
+            Ok(ExitCode::FAILURE)
+        } else {
+            println!("Configuration is valid");
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
@@ -1,7 +1,102 @@
-use crate::issuer::Issuer;
-use std::collections::HashMap;
+use crate::{
+    issuer::{Issuer, Problem},
+    server,
+};
+use std::collections::{HashMap, hash_map::Entry};
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+/// Names which are used by endpoints of the server, shadowing issuers of the same name
+const RESERVED_ISSUER_NAMES: &[&str] = &["version"];
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct Configuration {
     pub issuers: HashMap<String, Issuer>,
 }
+
+impl Configuration {
+    /// Merge another configuration into this one
+    ///
+    /// Fails if an issuer with the same name is already present.
+    pub fn merge(&mut self, other: Configuration) -> Result<(), server::Error> {
+        for (name, issuer) in other.issuers {
+            match self.issuers.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(issuer);
+                }
+                Entry::Occupied(entry) => {
+                    return Err(server::Error::DuplicateIssuer(entry.key().clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run semantic checks on all issuers, ordered by issuer name
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut issuers = self.issuers.iter().collect::<Vec<_>>();
+        issuers.sort_by_key(|(name, _)| *name);
+
+        issuers
+            .into_iter()
+            .flat_map(|(name, issuer)| {
+                let location = format!("issuers.{name}");
+                // the endpoints of the server take precedence over the ones of the issuers
+                let reserved = RESERVED_ISSUER_NAMES
+                    .contains(&name.as_str())
+                    .then(|| Problem {
+                        location: location.clone(),
+                        message: format!(
+                            "issuer name is reserved for an endpoint of the server: '{name}'"
+                        ),
+                    });
+                reserved.into_iter().chain(issuer.validate(&location))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_duplicate() -> anyhow::Result<()> {
+        let mut config = Configuration::default();
+        config
+            .issuers
+            .insert("a".into(), Issuer::new("key", ["openid"])?);
+
+        let mut other = Configuration::default();
+        other
+            .issuers
+            .insert("b".into(), Issuer::new("key", ["openid"])?);
+        config.merge(other)?;
+        assert_eq!(config.issuers.len(), 2);
+
+        let mut other = Configuration::default();
+        other
+            .issuers
+            .insert("a".into(), Issuer::new("key", ["openid"])?);
+        assert!(matches!(
+            config.merge(other),
+            Err(server::Error::DuplicateIssuer(name)) if name == "a"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reserved_issuer_name() -> anyhow::Result<()> {
+        let mut config = Configuration::default();
+        config.issuers.insert(
+            "version".into(),
+            Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?,
+        );
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].location, "issuers.version");
+
+        Ok(())
+    }
+}
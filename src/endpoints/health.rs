@@ -0,0 +1,108 @@
+use crate::server::state::ApplicationState;
+use actix_web::{HttpResponse, Responder, get, web};
+use serde_json::json;
+
+/// Liveness, succeeds as long as the server is able to respond
+#[get("/health/live")]
+pub async fn live() -> impl Responder {
+    HttpResponse::Ok().json(json!({"status": "up"}))
+}
+
+/// Readiness, fails once the audit log stopped writing events
+#[get("/health/ready")]
+pub async fn ready(app: web::Data<ApplicationState>) -> impl Responder {
+    if app.is_ready() {
+        HttpResponse::Ok().json(json!({"status": "up"}))
+    } else {
+        HttpResponse::ServiceUnavailable().json(json!({"status": "down"}))
+    }
+}
+
+/// Build information
+#[get("/version")]
+pub async fn version() -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "repository": env!("CARGO_PKG_REPOSITORY"),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        audit::{AuditEvent, AuditLog, EventKind, RequestId},
+        issuer::Issuer,
+        server::app::Application,
+    };
+    use actix_web::{App, http::StatusCode, test};
+    use std::{
+        collections::HashMap,
+        io::{self, Write},
+        time::Duration,
+    };
+
+    #[actix_web::test]
+    async fn ready() -> anyhow::Result<()> {
+        let issuers = HashMap::from([("test".to_string(), Issuer::new("key", ["openid"])?)]);
+        let app = Application::new("http://localhost".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        for uri in ["/health/live", "/health/ready", "/version"] {
+            let resp =
+                test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+        }
+
+        Ok(())
+    }
+
+    /// Writes fail by panicking, which stops the writer thread
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            panic!("broken audit log");
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn not_ready_without_audit_log() -> anyhow::Result<()> {
+        let issuers = HashMap::from([("test".to_string(), Issuer::new("key", ["openid"])?)]);
+        let mut app = Application::new("http://localhost".parse()?, None, issuers)?;
+        let audit = AuditLog::new(Broken);
+        app.audit(audit.clone());
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let ready = || test::TestRequest::get().uri("/health/ready").to_request();
+        let resp = test::call_service(&app, ready()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        audit.record(AuditEvent::new(
+            EventKind::Login,
+            "test",
+            &RequestId("broken".into()),
+        ));
+        for _ in 0..50 {
+            if !audit.is_running() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let resp = test::call_service(&app, ready()).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/health/live").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
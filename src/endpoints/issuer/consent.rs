@@ -0,0 +1,285 @@
+//! Asking the user for consent, and managing remembered consents
+
+use super::{
+    helper::validated_redirect, implicit::AuthorizationRequest, issuer_url, login::current_session,
+};
+use crate::{
+    audit::{AuditEvent, EventKind, RequestId},
+    endpoints::{Error, ErrorCode, response::escape},
+    issuer::{IssuerState, PendingConsent, Session},
+    server::state::ApplicationState,
+};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, delete, dev::ConnectionInfo, get, http::header, post, web,
+};
+use oxide_auth::primitives::scope::Scope;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use url::{Url, form_urlencoded};
+
+/// The outcome of checking the consent
+pub enum Consent {
+    Granted,
+    /// The user must be asked, using the contained page
+    Required(HttpResponse),
+}
+
+/// Check if the user has to consent to the scopes requested by the client
+pub fn check(
+    issuer: &IssuerState,
+    issuer_url: &Url,
+    req: &HttpRequest,
+    session: &Session,
+    client_id: &str,
+    scope: &Scope,
+    request: &AuthorizationRequest,
+) -> Result<Consent, (ErrorCode, &'static str)> {
+    let prompt = request.prompts();
+    let forced = prompt.contains("consent");
+
+    let required = forced
+        || issuer
+            .client(client_id)
+            .is_some_and(|client| client.settings().require_consent);
+    if !required {
+        return Ok(Consent::Granted);
+    }
+
+    let scopes = scope
+        .iter()
+        .map(ToString::to_string)
+        .collect::<BTreeSet<_>>();
+    if !forced
+        && issuer.consents.covers(
+            &session.subject,
+            client_id,
+            scopes.iter().map(String::as_str),
+        )
+    {
+        return Ok(Consent::Granted);
+    }
+
+    if prompt.contains("none") {
+        return Err((
+            ErrorCode::ConsentRequired,
+            "the user has not consented to the requested scopes",
+        ));
+    }
+
+    // coming back to the authorization endpoint must neither prompt nor log in again
+    let query = form_urlencoded::parse(req.query_string().as_bytes())
+        .filter(|(k, _)| k != "prompt" && k != "max_age");
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(query)
+        .finish();
+
+    let ticket = issuer.consents.request(PendingConsent {
+        session: session.id.clone(),
+        subject: session.subject.clone(),
+        client_id: client_id.to_string(),
+        scopes: scopes.clone(),
+        query,
+        redirect_uri: request.redirect_uri.clone(),
+        state: request.state.clone(),
+        response_type: request.response_type.clone(),
+        response_mode: request.response_mode.clone(),
+    });
+
+    Ok(Consent::Required(page(
+        issuer,
+        issuer_url,
+        &ticket,
+        &session.subject,
+        client_id,
+        &scopes,
+    )))
+}
+
+fn page(
+    issuer: &IssuerState,
+    issuer_url: &Url,
+    ticket: &str,
+    subject: &str,
+    client_id: &str,
+    scopes: &BTreeSet<String>,
+) -> HttpResponse {
+    let scopes = scopes
+        .iter()
+        .map(|scope| match issuer.scope_descriptions.get(scope) {
+            Some(description) => format!(
+                "<li><code>{}</code>: {}</li>",
+                escape(scope),
+                escape(description)
+            ),
+            None => format!("<li><code>{}</code></li>", escape(scope)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n        ");
+
+    let mut action = issuer_url.clone();
+    if let Ok(mut segments) = action.path_segments_mut() {
+        segments.push("consent");
+    }
+
+    HttpResponse::Ok()
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html>
+  <head><title>Consent</title></head>
+  <body>
+    <h1>Authorize {client_id}</h1>
+    <p>Signed in as <strong>{subject}</strong>. The application <strong>{client_id}</strong> requests access to:</p>
+    <form method="post" action="{action}">
+      <ul>
+        {scopes}
+      </ul>
+      <input type="hidden" name="ticket" value="{ticket}"/>
+      <button type="submit" name="decision" value="approve">Allow</button>
+      <button type="submit" name="decision" value="deny">Deny</button>
+    </form>
+  </body>
+</html>
+"#,
+            client_id = escape(client_id),
+            subject = escape(subject),
+            action = escape(action.as_str()),
+            ticket = escape(ticket),
+        ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Decision {
+    Approve,
+    Deny,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConsentForm {
+    pub ticket: String,
+    pub decision: Decision,
+}
+
+/// The decision of the user on the consent page
+#[post("/{issuer}/consent")]
+pub async fn consent_decision(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    path: web::Path<String>,
+    request_id: RequestId,
+    req: HttpRequest,
+    web::Form(form): web::Form<ConsentForm>,
+) -> Result<impl Responder, Error> {
+    let name = path.into_inner();
+
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+
+    let pending = issuer.consents.take_pending(&form.ticket).ok_or_else(|| {
+        Error::oauth(
+            ErrorCode::InvalidRequest,
+            "unknown or expired consent request",
+        )
+    })?;
+
+    if current_session(&issuer, &req).is_none_or(|session| session.id != pending.session) {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequest,
+            "consent must be given in the session it was requested in",
+        ));
+    }
+
+    let event = AuditEvent::new(EventKind::Consent, &name, &request_id)
+        .client_id(Some(&pending.client_id))
+        .subject(Some(&pending.subject))
+        .scopes(Some(
+            pending
+                .scopes
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ));
+
+    match form.decision {
+        Decision::Approve => {
+            server.audit().record(event);
+            issuer
+                .consents
+                .grant(&pending.subject, &pending.client_id, pending.scopes);
+
+            let mut location = issuer_url(&server, &conn, &name, ["auth"])?;
+            location.set_query(Some(&pending.query));
+
+            Ok(HttpResponse::SeeOther()
+                .append_header((header::LOCATION, location.to_string()))
+                .finish())
+        }
+        Decision::Deny => {
+            server.audit().record(event.failure("access_denied"));
+
+            let request = AuthorizationRequest {
+                client_id: Some(pending.client_id),
+                redirect_uri: pending.redirect_uri,
+                state: pending.state,
+                response_type: pending.response_type,
+                response_mode: pending.response_mode,
+                ..Default::default()
+            };
+            let base = issuer_url(&server, &conn, &name, [])?;
+
+            let error = Error::oauth(ErrorCode::AccessDenied, "the user denied the request");
+            let endpoint = &issuer.inner.read().await.endpoint;
+            match validated_redirect(
+                &endpoint.inner.registrar,
+                request.client_id.as_deref(),
+                request.redirect_uri.as_deref(),
+            ) {
+                Some(redirect_uri) => Err(error.redirect(
+                    redirect_uri,
+                    request.state.clone(),
+                    request
+                        .response_mode(&issuer.key, &base)
+                        .unwrap_or_default(),
+                )),
+                None => Err(error),
+            }
+        }
+    }
+}
+
+/// List the remembered consents of the logged in user
+#[get("/{issuer}/consents")]
+pub async fn list_consents(
+    server: web::Data<ApplicationState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<impl Responder, Error> {
+    let name = path.into_inner();
+
+    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let session = current_session(&issuer, &req).ok_or(Error::Unauthenticated)?;
+
+    Ok(web::Json(issuer.consents.list(&session.subject)))
+}
+
+/// Forget the consent of the logged in user for a client
+#[delete("/{issuer}/consents/{client_id}")]
+pub async fn revoke_consent(
+    server: web::Data<ApplicationState>,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<impl Responder, Error> {
+    let (name, client_id) = path.into_inner();
+
+    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let session = current_session(&issuer, &req).ok_or(Error::Unauthenticated)?;
+
+    match issuer.consents.revoke(&session.subject, &client_id) {
+        true => Ok(HttpResponse::NoContent().finish()),
+        false => Err(Error::UnknownResource(client_id)),
+    }
+}
@@ -0,0 +1,217 @@
+//! DPoP, sender-constrained access tokens (RFC 9449)
+
+use crate::{
+    endpoints::{Error, ErrorCode},
+    issuer::IssuerState,
+};
+use actix_web::{HttpRequest, HttpResponse, http::header};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use biscuit::{
+    ClaimsSet, Empty,
+    jwa::SignatureAlgorithm,
+    jwk::JWKSet,
+    jws::{Compact, Header},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use url::Url;
+
+/// How old a proof may be
+const PROOF_LIFETIME: Duration = Duration::minutes(5);
+/// How far a proof may be issued in the future, due to clocks not being in sync
+const CLOCK_SKEW: Duration = Duration::seconds(60);
+/// Lifetime of the nonces handed out to clients
+const NONCE_LIFETIME: Duration = Duration::minutes(5);
+
+/// The algorithms proofs may be signed with, only asymmetric ones make sense
+const ALGORITHMS: &[SignatureAlgorithm] = &[
+    SignatureAlgorithm::RS256,
+    SignatureAlgorithm::RS384,
+    SignatureAlgorithm::RS512,
+    SignatureAlgorithm::PS256,
+    SignatureAlgorithm::PS384,
+    SignatureAlgorithm::PS512,
+    SignatureAlgorithm::ES256,
+    SignatureAlgorithm::ES384,
+];
+
+/// A verified DPoP proof
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    /// The JWK thumbprint of the key the proof was signed with
+    pub jkt: String,
+}
+
+/// Verify the DPoP proof of a request, if it carries one
+///
+/// The proof must be issued for the method and URL of the request, and for the access token
+/// presented along with it.
+pub fn verify(
+    issuer: &IssuerState,
+    req: &HttpRequest,
+    url: &Url,
+    access_token: Option<&str>,
+    require_nonce: bool,
+) -> Result<Option<Proof>, Error> {
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidDpopProof, description);
+
+    let mut proofs = req.headers().get_all("DPoP");
+    let Some(proof) = proofs.next() else {
+        return Ok(None);
+    };
+    if proofs.next().is_some() {
+        return Err(invalid("only a single DPoP proof must be provided"));
+    }
+    let proof = proof
+        .to_str()
+        .map_err(|_| invalid("malformed DPoP proof"))?;
+
+    let token = Compact::<ClaimsSet<Map<String, Value>>, Empty>::new_encoded(proof);
+    let header: Header<Empty> = token
+        .unverified_header()
+        .map_err(|_| invalid("malformed DPoP proof"))?;
+    if header.registered.media_type.as_deref() != Some("dpop+jwt") {
+        return Err(invalid("DPoP proof must be of type dpop+jwt"));
+    }
+    if !ALGORITHMS.contains(&header.registered.algorithm) {
+        return Err(invalid("unsupported DPoP proof algorithm"));
+    }
+    let jwk = header
+        .registered
+        .web_key
+        .ok_or_else(|| invalid("DPoP proof is missing its key"))?;
+    let jkt = raw_jwk(proof)
+        .and_then(|jwk| thumbprint(&jwk))
+        .ok_or_else(|| invalid("DPoP proof must carry a public key"))?;
+
+    let token = token
+        .decode_with_jwks_ignore_kid(&JWKSet { keys: vec![jwk] })
+        .map_err(|err| {
+            log::info!("Failed to verify DPoP proof: {err}");
+            invalid("invalid DPoP proof signature")
+        })?;
+    let claims = token
+        .payload()
+        .map_err(|_| invalid("malformed DPoP proof"))?;
+    let claim = |name: &str| claims.private.get(name).and_then(Value::as_str);
+
+    let jti = claims
+        .registered
+        .id
+        .as_deref()
+        .ok_or_else(|| invalid("DPoP proof is missing its ID"))?;
+    if claim("htm") != Some(req.method().as_str()) {
+        return Err(invalid("DPoP proof was issued for a different method"));
+    }
+    let htu = claim("htu")
+        .and_then(|htu| Url::parse(htu).ok())
+        .map(|mut htu| {
+            htu.set_query(None);
+            htu.set_fragment(None);
+            htu
+        });
+    if htu.as_ref() != Some(url) {
+        return Err(invalid("DPoP proof was issued for a different URL"));
+    }
+
+    let now = Utc::now();
+    let issued = claims
+        .registered
+        .issued_at
+        .as_deref()
+        .copied()
+        .ok_or_else(|| invalid("DPoP proof is missing its issue time"))?;
+    if issued < now - PROOF_LIFETIME || issued > now + CLOCK_SKEW {
+        return Err(invalid("DPoP proof is too old or issued in the future"));
+    }
+
+    if let Some(access_token) = access_token {
+        if claim("ath") != Some(hash(access_token).as_str()) {
+            return Err(invalid(
+                "DPoP proof was issued for a different access token",
+            ));
+        }
+    }
+
+    if require_nonce && !claim("nonce").is_some_and(|nonce| issuer.dpop.valid_nonce(nonce)) {
+        return Err(Error::DpopNonce(issuer.dpop.nonce(NONCE_LIFETIME)));
+    }
+
+    if !issuer.dpop.use_proof(jti, expiry(issued)) {
+        return Err(invalid("DPoP proof was used before"));
+    }
+
+    Ok(Some(Proof { jkt }))
+}
+
+/// Respond to a request for a protected resource with an error, asking for DPoP
+pub fn challenge(err: &Error) -> HttpResponse {
+    let algs = ALGORITHMS
+        .iter()
+        .map(|alg| format!("{alg:?}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let code: &'static str = err.code().into();
+
+    let mut response = HttpResponse::Unauthorized();
+    response.append_header((
+        header::WWW_AUTHENTICATE,
+        format!(
+            r#"DPoP error="{code}", error_description="{}", algs="{algs}""#,
+            err.description().replace('"', "'")
+        ),
+    ));
+    if let Error::DpopNonce(nonce) = err {
+        response.append_header(("DPoP-Nonce", nonce.as_str()));
+    }
+    response.finish()
+}
+
+/// The hash of an access token, as used by the `ath` claim
+fn hash(access_token: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(access_token))
+}
+
+/// Until when the ID of a proof must be remembered, afterwards it is too old anyway
+fn expiry(issued: DateTime<Utc>) -> DateTime<Utc> {
+    issued + PROOF_LIFETIME + CLOCK_SKEW
+}
+
+/// Get the JWK of the proof, as it was sent
+fn raw_jwk(proof: &str) -> Option<Map<String, Value>> {
+    let header = BASE64_URL_SAFE_NO_PAD
+        .decode(proof.split('.').next()?)
+        .ok()?;
+    let mut header: Map<String, Value> = serde_json::from_slice(&header).ok()?;
+    match header.remove("jwk")? {
+        Value::Object(jwk) => Some(jwk),
+        _ => None,
+    }
+}
+
+/// The JWK SHA-256 thumbprint of a public key (RFC 7638)
+///
+/// Returns `None` for anything but a public key.
+fn thumbprint(jwk: &Map<String, Value>) -> Option<String> {
+    let members: &[&str] = match jwk.get("kty")?.as_str()? {
+        "RSA" => &["e", "kty", "n"],
+        "EC" => &["crv", "kty", "x", "y"],
+        _ => return None,
+    };
+    if ["d", "p", "q", "dp", "dq", "qi", "oth"]
+        .iter()
+        .any(|private| jwk.contains_key(*private))
+    {
+        return None;
+    }
+
+    let canonical = members
+        .iter()
+        .map(|name| Some((*name, jwk.get(*name)?.as_str()?)))
+        .collect::<Option<BTreeMap<_, _>>>()?;
+    let canonical = serde_json::to_string(&canonical).ok()?;
+
+    Some(BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(canonical)))
+}
@@ -1,28 +1,48 @@
 use crate::{
-    endpoints::{Error, issuer::issuer_url},
-    extensions::ConnectionInformation,
-    issuer::{IssuerState, JwtIdGenerator},
+    audit::{AuditEvent, EventKind, RequestId},
+    endpoints::{Error, issuer::issuer_url, response::ResponseMode},
+    extensions::{
+        Authentication, AuthenticationData, CertificateBinding, ConnectionInformation, DpopBinding,
+        Resources,
+    },
+    issuer::{ClaimTarget, IdTokenBinding, IssuerState, JwtIdGenerator, TokenInformation},
+    metrics::UNKNOWN_CLIENT,
+    oidc::Confirmation,
     server::state::ApplicationState,
 };
-use actix_web::dev::ConnectionInfo;
+use actix_web::{HttpRequest, HttpResponse, Responder, dev::ConnectionInfo, http::header};
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use chrono::Utc;
 use openidconnect::IssuerUrl;
 use oxide_auth::endpoint::WebResponse;
+use oxide_auth::primitives::{
+    registrar::{ClientUrl, ExactUrl},
+    scope::Scope,
+};
 use oxide_auth::{
-    endpoint::{Endpoint, OwnerSolicitor},
+    endpoint::{Endpoint, Issuer, OwnerSolicitor, QueryParameter, Registrar},
     frontends::simple::{
         endpoint::{ErrorInto, Generic},
-        extensions::{AddonList, Extended},
+        extensions::{AddonList, Extended, Pkce},
     },
 };
 use oxide_auth_actix::{OAuthRequest, OAuthResponse, WebError};
 use serde_json::Value;
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
+use url::Url;
 
 /// take a token response and add an id token
+///
+/// The ID token is created from the grant of the access token, which carries the information
+/// about the authentication from the authorization request.
 pub fn amend_id_token(
     mut resp: OAuthResponse,
     server: &ApplicationState,
     issuer: &IssuerState,
+    tokens: &impl Issuer,
     conn: &ConnectionInfo,
     issuer_name: &str,
 ) -> Result<OAuthResponse, Error> {
@@ -33,15 +53,47 @@ pub fn amend_id_token(
         return Ok(resp);
     };
 
-    let Some(_access_token) = value["access_token"].as_str() else {
+    let Some(access_token) = value["access_token"].as_str() else {
         return Ok(resp);
     };
 
     let base = issuer_url(server, conn, issuer_name, [])?;
+    let generator = JwtIdGenerator::new(issuer.key.clone(), IssuerUrl::from_url(base));
 
-    let id_token = JwtIdGenerator::new(issuer.key.clone(), IssuerUrl::from_url(base))
-        .create()
-        .map_err(|err| Error::Generic(err.to_string()))?;
+    let id_token = match tokens.recover_token(access_token).ok().flatten() {
+        Some(grant) => {
+            let authentication = Authentication::from_grant(&grant);
+            let lifetime = issuer.lifetimes.client(&grant.client_id).id_token();
+            let mut additional = issuer.claims.claims(
+                ClaimTarget::IdToken,
+                &grant.owner_id,
+                Some(&grant.client_id),
+                Some(&grant.scope),
+            );
+            if let Some(sid) = authentication.as_ref().and_then(|auth| auth.sid.clone()) {
+                additional.insert("sid".to_string(), sid.into());
+            }
+            generator.mint_bound(
+                &TokenInformation {
+                    subject: grant.owner_id,
+                    client_id: Some(grant.client_id.clone()),
+                    audience: vec![grant.client_id],
+                    scope: grant.scope.to_string(),
+                    expiry: Utc::now() + lifetime,
+                    auth_time: authentication.as_ref().and_then(|auth| auth.auth_time()),
+                    additional,
+                    confirmation: None,
+                },
+                &IdTokenBinding {
+                    nonce: authentication.and_then(|auth| auth.nonce),
+                    access_token: Some(access_token.to_string()),
+                    code: None,
+                },
+            )
+        }
+        None => generator.create(issuer.lifetimes.issuer().id_token()),
+    }
+    .map_err(|err| Error::Generic(err.to_string()))?;
 
     value["id_token"] = serde_json::to_value(id_token)?;
 
@@ -50,7 +102,7 @@ pub fn amend_id_token(
     Ok(resp)
 }
 
-pub fn with_conninfo<Inner>(inner: Inner, conn: ConnectionInfo) -> Extended<Inner, AddonList> {
+fn addons(conn: ConnectionInfo) -> AddonList {
     log::debug!("Adding conninfo: {conn:?}");
 
     let conn = Arc::new(ConnectionInformation(conn));
@@ -58,10 +110,75 @@ pub fn with_conninfo<Inner>(inner: Inner, conn: ConnectionInfo) -> Extended<Inne
     let mut addons = AddonList::new();
     addons.push_access_token(conn.clone());
     addons.push_client_credentials(conn);
+    // PKCE is optional, but verified if the client uses it
+    addons.push_code(Pkce::optional());
+    addons.push_access_token(Authentication(None));
+    let resources = Arc::new(Resources::default());
+    addons.push_access_token(resources.clone());
+    addons.push_client_credentials(resources);
+
+    addons
+}
+
+pub fn with_conninfo<Inner>(inner: Inner, conn: ConnectionInfo) -> Extended<Inner, AddonList> {
+    Extended::extend_with(inner, addons(conn))
+}
 
+/// Like [`with_conninfo`], additionally storing the authentication and the requested resources
+/// with the authorization code
+pub fn with_authentication<Inner>(
+    inner: Inner,
+    conn: ConnectionInfo,
+    authentication: AuthenticationData,
+    resources: Vec<String>,
+) -> Extended<Inner, AddonList> {
+    let mut addons = addons(conn);
+    addons.push_authorization(Authentication(Some(authentication)));
+    addons.push_authorization(Resources(resources));
     Extended::extend_with(inner, addons)
 }
 
+/// Like [`with_conninfo`], additionally binding the access token to a DPoP key or certificate
+pub fn with_binding<Inner>(
+    inner: Inner,
+    conn: ConnectionInfo,
+    confirmation: Confirmation,
+) -> Extended<Inner, AddonList> {
+    let mut addons = addons(conn);
+    let dpop = Arc::new(DpopBinding(confirmation.jkt));
+    addons.push_access_token(dpop.clone());
+    addons.push_client_credentials(dpop);
+    let certificate = Arc::new(CertificateBinding(confirmation.x5t_s256));
+    addons.push_access_token(certificate.clone());
+    addons.push_client_credentials(certificate);
+    Extended::extend_with(inner, addons)
+}
+
+/// Set the token type of a token response to `DPoP`, if the access token is bound to a key
+pub fn amend_token_type(
+    mut resp: OAuthResponse,
+    tokens: &impl Issuer,
+) -> Result<OAuthResponse, Error> {
+    let Some(Ok(mut value)) = resp
+        .get_body()
+        .map(|body| serde_json::from_str::<Value>(&body))
+    else {
+        return Ok(resp);
+    };
+
+    let bound = value["access_token"]
+        .as_str()
+        .and_then(|access_token| tokens.recover_token(access_token).ok().flatten())
+        .and_then(|grant| DpopBinding::from_grant(&grant))
+        .is_some();
+    if bound {
+        value["token_type"] = "DPoP".into();
+        resp.body_json(&serde_json::to_string(&value)?)?;
+    }
+
+    Ok(resp)
+}
+
 pub fn with_solicitor<S>(
     endpoint: &mut Extended<crate::issuer::Endpoint, AddonList>,
     solicitor: S,
@@ -81,3 +198,257 @@ where
         addons: &mut endpoint.addons,
     })
 }
+
+/// Get the client ID of a request, either from basic authentication or from the parameters
+pub fn client_id(req: &OAuthRequest) -> Option<String> {
+    req.authorization_header()
+        .and_then(|auth| auth.strip_prefix("Basic "))
+        .and_then(|auth| BASE64_STANDARD.decode(auth).ok())
+        .and_then(|auth| String::from_utf8(auth).ok())
+        .and_then(|auth| {
+            auth.split_once(':')
+                .map(|(client_id, _)| client_id.to_string())
+        })
+        .or_else(|| {
+            req.body()
+                .and_then(|body| body.unique_value("client_id"))
+                .or_else(|| {
+                    req.query()
+                        .and_then(|query| query.unique_value("client_id"))
+                })
+                .map(|client_id| client_id.into_owned())
+        })
+}
+
+/// Get the client credentials of a request, either from basic authentication or from the body
+pub fn client_credentials(req: &OAuthRequest) -> Option<(String, Option<String>)> {
+    if let Some(auth) = req.authorization_header() {
+        let (client_id, secret) = basic_credentials(auth)?;
+        return Some((client_id, Some(secret)));
+    }
+
+    let body = req.body()?;
+    let client_id = body.unique_value("client_id")?.into_owned();
+    let secret = body
+        .unique_value("client_secret")
+        .map(|secret| secret.into_owned());
+
+    Some((client_id, secret))
+}
+
+/// Decode the client ID and secret of a basic authorization header
+pub fn basic_credentials(auth: &str) -> Option<(String, String)> {
+    let auth = BASE64_STANDARD.decode(auth.strip_prefix("Basic ")?).ok()?;
+    String::from_utf8(auth)
+        .ok()?
+        .split_once(':')
+        .map(|(client_id, secret)| (client_id.to_string(), secret.to_string()))
+}
+
+/// Record the outcome of a token request
+pub fn record_token(
+    server: &ApplicationState,
+    issuer: &str,
+    request_id: &RequestId,
+    client_id: Option<&str>,
+    grant_type: &str,
+    resp: &Result<OAuthResponse, Error>,
+) {
+    let metrics = server.metrics();
+
+    let kind = match grant_type {
+        "refresh_token" => EventKind::Refresh,
+        _ => EventKind::TokenIssued,
+    };
+    let event = AuditEvent::new(kind, issuer, request_id).client_id(client_id);
+
+    let body = match resp {
+        Ok(resp) => resp
+            .get_body()
+            .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+            .unwrap_or_default(),
+        Err(_) => {
+            metrics
+                .token_errors
+                .with_label_values(&[issuer, "server_error"])
+                .inc();
+            server.audit().record(event.failure("server_error"));
+            return;
+        }
+    };
+
+    let client_id = client_id.unwrap_or_default();
+    let client = match server.issuer(issuer) {
+        Some(issuer) => issuer.client_label(client_id),
+        None => UNKNOWN_CLIENT,
+    };
+
+    if let Some(error) = body["error"].as_str() {
+        metrics
+            .token_errors
+            .with_label_values(&[issuer, error])
+            .inc();
+        if error == "invalid_client" {
+            metrics
+                .client_authentication_failures
+                .with_label_values(&[issuer, client])
+                .inc();
+        }
+        server.audit().record(event.failure(error));
+    } else if let Some(access_token) = body["access_token"].as_str() {
+        metrics
+            .tokens_issued
+            .with_label_values(&[issuer, client, grant_type])
+            .inc();
+        server.audit().record(
+            event
+                .subject(token_subject(access_token))
+                .scopes(body["scope"].as_str()),
+        );
+    }
+}
+
+/// Record the outcome of an authorization request
+pub fn record_authorization(
+    server: &ApplicationState,
+    issuer: &str,
+    request_id: &RequestId,
+    client_id: Option<&str>,
+    subject: Option<&str>,
+    scopes: Option<&str>,
+    resp: &Result<OAuthResponse, Error>,
+) {
+    let event = AuditEvent::new(EventKind::CodeIssued, issuer, request_id)
+        .client_id(client_id)
+        .scopes(scopes);
+
+    let location = match resp {
+        Ok(resp) => resp
+            .get_headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| Url::parse(location).ok()),
+        Err(err) => {
+            server
+                .audit()
+                .record(event.failure(<&str>::from(err.code())));
+            return;
+        }
+    };
+
+    let Some(location) = location else {
+        // not redirected, so there is no outcome to report
+        return;
+    };
+
+    let mut query = location.query_pairs();
+    if let Some((_, error)) = query.find(|(k, _)| k == "error") {
+        server.audit().record(event.failure(error));
+    } else if location.query_pairs().any(|(k, _)| k == "code") {
+        server.audit().record(event.subject(subject));
+    }
+}
+
+/// Get the subject of a token we issued, without verifying it
+fn token_subject(token: &str) -> Option<String> {
+    let payload = token.split('.').nth(1)?;
+    let payload = BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let payload: Value = serde_json::from_slice(&payload).ok()?;
+    payload["sub"].as_str().map(ToString::to_string)
+}
+
+/// Get the redirect URI of an authorization request, only if it is valid for the client
+///
+/// Errors must only be delivered by redirecting if this returns a URI.
+pub fn validated_redirect(
+    registrar: &impl Registrar,
+    client_id: Option<&str>,
+    redirect_uri: Option<&str>,
+) -> Option<Url> {
+    let redirect_uri = match redirect_uri {
+        Some(uri) => Some(Cow::Owned(uri.parse::<ExactUrl>().ok()?)),
+        None => None,
+    };
+
+    registrar
+        .bound_redirect(ClientUrl {
+            client_id: client_id?.into(),
+            redirect_uri,
+        })
+        .ok()
+        .map(|bound| bound.redirect_uri.to_url())
+}
+
+/// Deliver the result of an oxide-auth authorization using the requested response mode
+///
+/// oxide-auth always appends the parameters to the query of the redirect URI, so they have to be
+/// moved for other response modes. A successful response is amended with the `session_state`.
+pub fn redeliver(
+    resp: OAuthResponse,
+    response_mode: ResponseMode,
+    session_state: Option<&str>,
+    req: &HttpRequest,
+) -> HttpResponse {
+    const PARAMS: &[&str] = &["code", "state", "error", "error_description", "error_uri"];
+
+    if matches!(response_mode, ResponseMode::Query) && session_state.is_none() {
+        return resp.respond_to(req);
+    }
+
+    let Some(location) = resp
+        .get_headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| Url::parse(location).ok())
+    else {
+        return resp.respond_to(req);
+    };
+
+    let (mut params, other): (Vec<_>, Vec<_>) = location
+        .query_pairs()
+        .into_owned()
+        .partition(|(k, _)| PARAMS.contains(&k.as_str()));
+
+    if let Some(session_state) = session_state {
+        if params.iter().any(|(k, _)| k == "code") {
+            params.push(("session_state".into(), session_state.into()));
+        }
+    }
+
+    let mut redirect_uri = location.clone();
+    redirect_uri.set_query(None);
+    if !other.is_empty() {
+        redirect_uri.query_pairs_mut().extend_pairs(other);
+    }
+
+    response_mode.deliver(
+        &redirect_uri,
+        params.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+    )
+}
+
+/// Get the scope the client would be granted, if the client and redirect URI are valid
+pub fn negotiated_scope(
+    registrar: &impl Registrar,
+    client_id: &str,
+    redirect_uri: Option<&str>,
+    scope: Option<&str>,
+) -> Option<Scope> {
+    let redirect_uri = match redirect_uri {
+        Some(uri) => Some(Cow::Owned(uri.parse::<ExactUrl>().ok()?)),
+        None => None,
+    };
+    let scope = scope.map(str::parse).transpose().ok()?;
+
+    let bound = registrar
+        .bound_redirect(ClientUrl {
+            client_id: client_id.into(),
+            redirect_uri,
+        })
+        .ok()?;
+
+    registrar
+        .negotiate(bound, scope)
+        .ok()
+        .map(|pre_grant| pre_grant.scope)
+}
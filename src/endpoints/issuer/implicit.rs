@@ -0,0 +1,458 @@
+//! The implicit and hybrid flows, which oxide-auth doesn't support
+//!
+//! Tokens are returned directly from the authorization endpoint. Other than for the code flow,
+//! the default response mode is `fragment`.
+
+use super::{
+    consent::{self, Consent},
+    issuer_url,
+    login::{LoginRequest, login},
+    session_management::session_state,
+};
+use crate::{
+    audit::{AuditEvent, EventKind, RequestId},
+    endpoints::{Error, ErrorCode, response::ResponseMode},
+    extensions::{Authentication, AuthenticationData, ConnectionInformation, Resources},
+    issuer::{
+        ClaimTarget, IdTokenBinding, IssuerState, JwtIdGenerator, JwtResponseGenerator,
+        TokenInformation, is_valid_resource,
+    },
+    secrets::Key,
+    server::state::ApplicationState,
+};
+use actix_web::{HttpRequest, HttpResponse, dev::ConnectionInfo};
+use chrono::Utc;
+use openidconnect::IssuerUrl;
+use oxide_auth::{
+    frontends::simple::extensions::Pkce,
+    primitives::{
+        grant::{Extensions, Grant, Value},
+        prelude::*,
+        registrar::{ClientUrl, ExactUrl},
+    },
+};
+use std::{borrow::Cow, collections::BTreeSet};
+use url::Url;
+
+/// The parameters of an authorization request
+#[derive(Clone, Debug, Default)]
+pub struct AuthorizationRequest {
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub response_type: String,
+    pub response_mode: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub prompt: Option<String>,
+    pub max_age: Option<String>,
+    pub login_hint: Option<String>,
+    /// The pushed request the parameters were taken from
+    pub request_uri: Option<String>,
+    /// The resources the access token is requested for (RFC 8707), which may be repeated
+    pub resources: Vec<String>,
+}
+
+impl AuthorizationRequest {
+    /// Create the request from its parameters
+    pub fn from_query(query: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            client_id: query("client_id"),
+            redirect_uri: query("redirect_uri"),
+            response_type: query("response_type").unwrap_or_default(),
+            response_mode: query("response_mode"),
+            scope: query("scope"),
+            state: query("state"),
+            nonce: query("nonce"),
+            code_challenge: query("code_challenge"),
+            code_challenge_method: query("code_challenge_method"),
+            prompt: query("prompt"),
+            max_age: query("max_age"),
+            login_hint: query("login_hint"),
+            request_uri: query("request_uri"),
+            resources: query("resource").into_iter().collect(),
+        }
+    }
+
+    /// Check that the client may request access tokens for the requested resources
+    pub fn check_resources(&self, issuer: &IssuerState) -> Result<(), (ErrorCode, &'static str)> {
+        let client_id = self.client_id.as_deref().unwrap_or_default();
+        for resource in &self.resources {
+            if !is_valid_resource(resource) {
+                return Err((ErrorCode::InvalidTarget, "invalid resource"));
+            }
+            if !issuer.audiences.allows_resource(client_id, resource) {
+                return Err((
+                    ErrorCode::InvalidTarget,
+                    "the client may not request the resource",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn prompts(&self) -> BTreeSet<&str> {
+        self.prompt
+            .as_deref()
+            .map(|prompt| prompt.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    /// The requested response mode, or the default of the flow
+    ///
+    /// The JWT response modes are signed with the key of the issuer. Returns `None` if the
+    /// requested response mode is unknown.
+    pub fn response_mode(&self, key: &Key, issuer_url: &Url) -> Option<ResponseMode> {
+        let default = match self.is_implicit() {
+            true => ResponseMode::Fragment,
+            false => ResponseMode::Query,
+        };
+        let jwt = |mode: ResponseMode| {
+            mode.jwt(JwtResponseGenerator::new(
+                key.clone(),
+                issuer_url.clone(),
+                self.client_id.clone().unwrap_or_default(),
+            ))
+        };
+
+        match self.response_mode.as_deref() {
+            None => Some(default),
+            Some("jwt") => Some(jwt(default)),
+            Some(response_mode) => match response_mode.strip_suffix(".jwt") {
+                Some(response_mode) => ResponseMode::parse(response_mode).map(jwt),
+                None => ResponseMode::parse(response_mode),
+            },
+        }
+    }
+
+    pub fn response_types(&self) -> BTreeSet<&str> {
+        self.response_type.split_whitespace().collect()
+    }
+
+    pub fn login(&self) -> LoginRequest<'_> {
+        LoginRequest {
+            prompt: self.prompt.as_deref(),
+            max_age: self.max_age.as_deref(),
+            login_hint: self.login_hint.as_deref(),
+        }
+    }
+
+    /// If the request requires the implicit or hybrid flow
+    pub fn is_implicit(&self) -> bool {
+        let types = self.response_types();
+        types.contains("token") || types.contains("id_token")
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn authorize(
+    server: &ApplicationState,
+    issuer: &IssuerState,
+    req: &HttpRequest,
+    conn: &ConnectionInfo,
+    name: &str,
+    request_id: &RequestId,
+    request: AuthorizationRequest,
+) -> Result<HttpResponse, Error> {
+    let endpoint = &mut issuer.inner.write().await.endpoint;
+    let endpoint = &mut endpoint.inner;
+
+    let redirect_uri = match request.redirect_uri.as_deref() {
+        Some(uri) => Some(Cow::Owned(uri.parse::<ExactUrl>().map_err(|_| {
+            Error::oauth(ErrorCode::InvalidRequest, "invalid redirect URI")
+        })?)),
+        None => None,
+    };
+    let client_id = request
+        .client_id
+        .as_deref()
+        .ok_or_else(|| Error::oauth(ErrorCode::InvalidRequest, "missing client_id"))?;
+    let bound = endpoint
+        .registrar
+        .bound_redirect(ClientUrl {
+            client_id: client_id.into(),
+            redirect_uri,
+        })
+        .map_err(|_| {
+            Error::oauth(
+                ErrorCode::InvalidRequest,
+                "unknown client or invalid redirect URI",
+            )
+        })?;
+
+    // from here on, errors are delivered to the client
+    let redirect = bound.redirect_uri.to_url();
+    let issuer_url = issuer_url(server, conn, name, [])?;
+    let response_mode = request.response_mode(&issuer.key, &issuer_url);
+    let fail = |code, description: &str| {
+        Error::oauth(code, description).redirect(
+            redirect.clone(),
+            request.state.clone(),
+            response_mode.clone().unwrap_or(ResponseMode::Fragment),
+        )
+    };
+
+    let response_mode = match response_mode.clone() {
+        None => return Err(fail(ErrorCode::InvalidRequest, "unknown response mode")),
+        // tokens must not end up in the query, even when wrapped into a JWT
+        Some(response_mode) if matches!(response_mode.delivery(), ResponseMode::Query) => {
+            return Err(fail(
+                ErrorCode::InvalidRequest,
+                "the query response mode must not be used when returning tokens",
+            ));
+        }
+        Some(response_mode) => response_mode,
+    };
+
+    let types = request.response_types();
+    let (code, token, id_token) = (
+        types.contains("code"),
+        types.contains("token"),
+        types.contains("id_token"),
+    );
+    if types
+        .iter()
+        .any(|t| !matches!(*t, "code" | "token" | "id_token"))
+    {
+        return Err(fail(
+            ErrorCode::UnsupportedResponseType,
+            "unsupported response type",
+        ));
+    }
+
+    if !issuer
+        .client(client_id)
+        .is_some_and(|client| client.settings().allow_implicit)
+    {
+        return Err(fail(
+            ErrorCode::UnauthorizedClient,
+            "client is not allowed to use the implicit or hybrid flow",
+        ));
+    }
+
+    if id_token && request.nonce.is_none() {
+        return Err(fail(ErrorCode::InvalidRequest, "missing nonce"));
+    }
+
+    let scope = request
+        .scope
+        .as_deref()
+        .map(str::parse::<Scope>)
+        .transpose()
+        .map_err(|_| fail(ErrorCode::InvalidScope, "invalid scope"))?;
+    let pre_grant = endpoint
+        .registrar
+        .negotiate(bound, scope)
+        .map_err(|_| fail(ErrorCode::InvalidScope, "scope not allowed for client"))?;
+
+    if id_token && !pre_grant.scope.iter().any(|scope| scope == "openid") {
+        return Err(fail(
+            ErrorCode::InvalidScope,
+            "the openid scope is required for an ID token",
+        ));
+    }
+
+    request
+        .check_resources(issuer)
+        .map_err(|(code, description)| fail(code, description))?;
+
+    let scope = pre_grant.scope.to_string();
+
+    let login = login(issuer, req, &request.login()).map_err(|(code, description)| {
+        server.audit().record(
+            AuditEvent::new(EventKind::Login, name, request_id)
+                .client_id(Some(client_id))
+                .failure(<&str>::from(code)),
+        );
+        fail(code, description)
+    })?;
+    let subject = login.session.subject.as_str();
+
+    match consent::check(
+        issuer,
+        &issuer_url,
+        req,
+        &login.session,
+        client_id,
+        &pre_grant.scope,
+        &request,
+    )
+    .map_err(|(code, description)| fail(code, description))?
+    {
+        Consent::Granted => {}
+        Consent::Required(mut page) => {
+            login.apply(&mut page, &issuer_url)?;
+            return Ok(page);
+        }
+    }
+
+    // the pushed request was needed up to the consent of the user
+    if let Some(request_uri) = &request.request_uri {
+        issuer.pushed.remove(request_uri);
+    }
+    issuer.sessions.add_client(&login.session.id, client_id);
+
+    if login.created {
+        server.audit().record(
+            AuditEvent::new(EventKind::Login, name, request_id)
+                .client_id(Some(client_id))
+                .subject(Some(subject))
+                .scopes(Some(&scope)),
+        );
+    }
+
+    let lifetimes = issuer.lifetimes.client(client_id);
+    let grant = |until, extensions| Grant {
+        owner_id: subject.into(),
+        client_id: pre_grant.client_id.clone(),
+        scope: pre_grant.scope.clone(),
+        redirect_uri: pre_grant.redirect_uri.to_url(),
+        until,
+        extensions,
+    };
+
+    let resources = Resources(request.resources.clone());
+    let with_resources = |mut extensions: Extensions| {
+        if !resources.0.is_empty() {
+            extensions.set(&resources, resources.encode());
+        }
+        extensions
+    };
+
+    let mut params = vec![];
+
+    let code = match code {
+        true => {
+            let pkce = Pkce::optional();
+            let mut extensions = Extensions::new();
+            match pkce.challenge(
+                request.code_challenge_method.as_deref().map(Cow::Borrowed),
+                request.code_challenge.as_deref().map(Cow::Borrowed),
+            ) {
+                Ok(Some(challenge)) => extensions.set(&pkce, challenge),
+                Ok(None) => {}
+                Err(()) => return Err(fail(ErrorCode::InvalidRequest, "invalid code challenge")),
+            }
+            // carried on to the ID token of the token endpoint
+            let authentication = AuthenticationData {
+                auth_time: login.session.auth_time.timestamp(),
+                nonce: request.nonce.clone(),
+                sid: Some(login.session.sid.clone()),
+            };
+            extensions.set(
+                &Authentication(None),
+                Value::Private(serde_json::to_string(&authentication).ok()),
+            );
+
+            let code = endpoint
+                .authorizer
+                .authorize(grant(
+                    Utc::now() + lifetimes.authorization_code(),
+                    with_resources(extensions),
+                ))
+                .map_err(|()| fail(ErrorCode::ServerError, "failed to create code"))?;
+            params.push(("code", code.clone()));
+            Some(code)
+        }
+        false => None,
+    };
+
+    let access_token = match token {
+        true => {
+            let conninfo = ConnectionInformation(conn.clone());
+            let mut extensions = Extensions::new();
+            extensions.set(&conninfo, conninfo.encode());
+
+            let issued = endpoint
+                .issuer
+                .issue(grant(
+                    Utc::now() + lifetimes.access_token(),
+                    with_resources(extensions),
+                ))
+                .map_err(|()| fail(ErrorCode::ServerError, "failed to issue token"))?;
+            // the implicit flow must not return a refresh token
+            params.extend([
+                ("access_token", issued.token.clone()),
+                ("token_type", "Bearer".into()),
+                (
+                    "expires_in",
+                    (issued.until - Utc::now()).num_seconds().to_string(),
+                ),
+                ("scope", scope.clone()),
+            ]);
+            Some(issued.token)
+        }
+        false => None,
+    };
+
+    if id_token {
+        let mut additional = issuer.claims.claims(
+            ClaimTarget::IdToken,
+            subject,
+            Some(client_id),
+            Some(&pre_grant.scope),
+        );
+        additional.insert("sid".to_string(), login.session.sid.clone().into());
+        let id_token =
+            JwtIdGenerator::new(issuer.key.clone(), IssuerUrl::from_url(issuer_url.clone()))
+                .mint_bound(
+                    &TokenInformation {
+                        subject: subject.into(),
+                        client_id: Some(client_id.into()),
+                        audience: vec![client_id.into()],
+                        scope: scope.clone(),
+                        expiry: Utc::now() + lifetimes.id_token(),
+                        auth_time: Some(login.session.auth_time),
+                        additional,
+                        confirmation: None,
+                    },
+                    &IdTokenBinding {
+                        nonce: request.nonce.clone(),
+                        access_token: access_token.clone(),
+                        code: code.clone(),
+                    },
+                )
+                .map_err(|err| Error::Generic(err.to_string()))?;
+        params.push(("id_token", id_token));
+    }
+
+    if let Some(state) = &request.state {
+        params.push(("state", state.clone()));
+    }
+    params.push((
+        "session_state",
+        session_state(client_id, &redirect, &login.session.sid),
+    ));
+
+    if code.is_some() {
+        server.audit().record(
+            AuditEvent::new(EventKind::CodeIssued, name, request_id)
+                .client_id(Some(client_id))
+                .subject(Some(subject))
+                .scopes(Some(&scope)),
+        );
+    }
+    if access_token.is_some() || id_token {
+        server
+            .metrics()
+            .tokens_issued
+            .with_label_values(&[name, client_id, "implicit"])
+            .inc();
+        server.audit().record(
+            AuditEvent::new(EventKind::TokenIssued, name, request_id)
+                .client_id(Some(client_id))
+                .subject(Some(subject))
+                .scopes(Some(&scope)),
+        );
+    }
+
+    let mut response = response_mode.deliver(
+        &redirect,
+        params.iter().map(|(name, value)| (*name, value.as_str())),
+    );
+    login.apply(&mut response, &issuer_url)?;
+
+    Ok(response)
+}
@@ -0,0 +1,173 @@
+//! Login sessions of the user agent, used by the authorization endpoint
+
+use crate::{
+    endpoints::{Error, ErrorCode},
+    issuer::{IssuerState, Session},
+};
+use actix_web::{
+    HttpRequest, HttpResponse,
+    cookie::{Cookie, SameSite},
+};
+use chrono::{Duration, Utc};
+use std::collections::BTreeSet;
+use url::Url;
+
+/// The name of the session cookie, it is scoped to the path of the issuer
+pub const SESSION_COOKIE: &str = "garage_door_session";
+
+/// The name of the cookie holding the browser state of Session Management
+///
+/// Other than the session cookie, it must be readable by the `check_session` iframe. It holds the
+/// public session ID.
+pub const BROWSER_STATE_COOKIE: &str = "garage_door_browser_state";
+
+/// The parameters of an authorization request relevant for the login
+#[derive(Clone, Debug, Default)]
+pub struct LoginRequest<'a> {
+    pub prompt: Option<&'a str>,
+    pub max_age: Option<&'a str>,
+    pub login_hint: Option<&'a str>,
+}
+
+/// The outcome of a successful login
+#[derive(Clone, Debug)]
+pub struct Login {
+    pub session: Session,
+    /// If the session was created by this request, and the cookie must be set
+    pub created: bool,
+}
+
+impl Login {
+    /// The session cookie, scoped to the issuer
+    pub fn cookie(&self, issuer_url: &Url) -> Cookie<'static> {
+        Cookie::build(SESSION_COOKIE, self.session.id.clone())
+            .path(issuer_url.path().to_string())
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .finish()
+    }
+
+    /// The browser state cookie, readable by scripts
+    ///
+    /// The `check_session` iframe is embedded by the client, so the cookie must be available in a
+    /// cross-site context. Browsers only allow this for secure cookies.
+    pub fn browser_state_cookie(&self, issuer_url: &Url) -> Cookie<'static> {
+        let secure = issuer_url.scheme() == "https";
+        Cookie::build(BROWSER_STATE_COOKIE, self.session.sid.clone())
+            .path(issuer_url.path().to_string())
+            .secure(secure)
+            .same_site(match secure {
+                true => SameSite::None,
+                false => SameSite::Lax,
+            })
+            .finish()
+    }
+
+    /// Set the session cookies on the response, if the session is new
+    pub fn apply(&self, response: &mut HttpResponse, issuer_url: &Url) -> Result<(), Error> {
+        if self.created {
+            for cookie in [
+                self.cookie(issuer_url),
+                self.browser_state_cookie(issuer_url),
+            ] {
+                response
+                    .add_cookie(&cookie)
+                    .map_err(|err| Error::Generic(err.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove the session cookies from the user agent
+pub fn clear_cookie(response: &mut HttpResponse, issuer_url: &Url) -> Result<(), Error> {
+    for name in [SESSION_COOKIE, BROWSER_STATE_COOKIE] {
+        let cookie = Cookie::build(name, "")
+            .path(issuer_url.path().to_string())
+            .finish();
+        response
+            .add_removal_cookie(&cookie)
+            .map_err(|err| Error::Generic(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Get the current session of the user agent
+pub fn current_session(issuer: &IssuerState, req: &HttpRequest) -> Option<Session> {
+    req.cookie(SESSION_COOKIE)
+        .and_then(|cookie| issuer.sessions.get(cookie.value()))
+}
+
+/// Reuse the session of the user agent, or authenticate the user
+///
+/// There is no login form, the user is authenticated automatically. It is the user named by the
+/// `login_hint`, the user of the previous session, or the first user of the issuer.
+pub fn login(
+    issuer: &IssuerState,
+    req: &HttpRequest,
+    request: &LoginRequest,
+) -> Result<Login, (ErrorCode, &'static str)> {
+    let prompt = request
+        .prompt
+        .map(|prompt| prompt.split_whitespace().collect::<BTreeSet<_>>())
+        .unwrap_or_default();
+    if prompt.contains("none") && prompt.len() > 1 {
+        return Err((
+            ErrorCode::InvalidRequest,
+            "prompt=none must not be combined with other values",
+        ));
+    }
+    let max_age = request
+        .max_age
+        .map(|max_age| max_age.parse::<u32>())
+        .transpose()
+        .map_err(|_| (ErrorCode::InvalidRequest, "invalid max_age"))?;
+    let login_hint = request
+        .login_hint
+        .filter(|login_hint| issuer.user(login_hint).is_some());
+
+    let current = current_session(issuer, req);
+
+    let valid = current.clone().filter(|session| {
+        !prompt.contains("login")
+            && max_age.is_none_or(|max_age| {
+                Utc::now() - session.auth_time <= Duration::seconds(max_age.into())
+            })
+    });
+
+    match valid {
+        Some(session) if login_hint.is_none_or(|hint| hint == session.subject) => {
+            return Ok(Login {
+                session,
+                created: false,
+            });
+        }
+        Some(_) if prompt.contains("none") => {
+            return Err((
+                ErrorCode::InteractionRequired,
+                "the session belongs to a different user",
+            ));
+        }
+        _ if prompt.contains("none") => {
+            return Err((ErrorCode::LoginRequired, "no valid login session"));
+        }
+        _ => {}
+    }
+
+    let Some(subject) = login_hint
+        .map(ToString::to_string)
+        .or_else(|| current.as_ref().map(|session| session.subject.clone()))
+        .or_else(|| issuer.users.first().map(|user| user.id.clone()))
+    else {
+        return Err((ErrorCode::AccessDenied, "the issuer has no users"));
+    };
+
+    if let Some(current) = current {
+        issuer.sessions.remove(&current.id);
+    }
+
+    Ok(Login {
+        session: issuer.sessions.create(subject),
+        created: true,
+    })
+}
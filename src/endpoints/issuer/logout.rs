@@ -0,0 +1,280 @@
+//! RP-initiated logout, notifying clients through the back and front channel
+
+use super::{
+    issuer_url,
+    login::{clear_cookie, current_session},
+};
+use crate::{
+    audit::{AuditEvent, EventKind, RequestId},
+    endpoints::{Error, ErrorCode, response::escape},
+    issuer::{JwtIdGenerator, verify},
+    server::state::ApplicationState,
+};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, dev::ConnectionInfo, get, http::header, post, web,
+};
+use biscuit::SingleOrMultiple;
+use futures_util::future::join_all;
+use openidconnect::IssuerUrl;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fmt::Write, time::Duration};
+use url::Url;
+
+/// Timeout for calling the back-channel logout URI of a client
+const BACKCHANNEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LogoutRequest {
+    pub id_token_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub state: Option<String>,
+}
+
+#[get("/{issuer}/logout")]
+pub async fn logout_get(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    path: web::Path<String>,
+    request_id: RequestId,
+    req: HttpRequest,
+    web::Query(request): web::Query<LogoutRequest>,
+) -> Result<impl Responder, Error> {
+    logout(server, conn, path, request_id, req, request).await
+}
+
+#[post("/{issuer}/logout")]
+pub async fn logout_post(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    path: web::Path<String>,
+    request_id: RequestId,
+    req: HttpRequest,
+    web::Form(request): web::Form<LogoutRequest>,
+) -> Result<impl Responder, Error> {
+    logout(server, conn, path, request_id, req, request).await
+}
+
+async fn logout(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    path: web::Path<String>,
+    request_id: RequestId,
+    req: HttpRequest,
+    request: LogoutRequest,
+) -> Result<HttpResponse, Error> {
+    let name = path.into_inner();
+
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+    let issuer_url = issuer_url(&server, &conn, &name, [])?;
+
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidRequest, description);
+
+    // the hint may have expired, but it must have been issued by us
+    let hint = request
+        .id_token_hint
+        .as_deref()
+        .map(|hint| verify(&issuer.key, hint))
+        .transpose()
+        .ok()
+        .flatten()
+        .filter(|claims| claims.registered.issuer.as_deref() == Some(issuer_url.as_str()));
+    if request.id_token_hint.is_some() && hint.is_none() {
+        return Err(invalid("invalid id_token_hint"));
+    }
+
+    let hint_client = hint.as_ref().and_then(|claims| {
+        claims
+            .private
+            .get("azp")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .or_else(|| match &claims.registered.audience {
+                Some(SingleOrMultiple::Single(aud)) => Some(aud.clone()),
+                _ => None,
+            })
+    });
+    let client_id = match (hint_client, request.client_id) {
+        (Some(hint), Some(client_id)) if hint != client_id => {
+            return Err(invalid("client_id doesn't match the id_token_hint"));
+        }
+        (hint, client_id) => hint.or(client_id),
+    };
+
+    let redirect = match request.post_logout_redirect_uri {
+        Some(uri) => {
+            let client = client_id
+                .as_deref()
+                .and_then(|client_id| issuer.client(client_id))
+                .ok_or_else(|| invalid("the client must be identified to redirect after logout"))?;
+            let uri = Url::parse(&uri).map_err(|_| invalid("invalid post_logout_redirect_uri"))?;
+            if !client.settings().post_logout_redirect_urls.contains(&uri) {
+                return Err(invalid(
+                    "post_logout_redirect_uri is not registered for the client",
+                ));
+            }
+            Some(uri)
+        }
+        None => None,
+    };
+
+    let session = current_session(&issuer, &req);
+    if let Some(session) = &session {
+        issuer.sessions.remove(&session.id);
+    }
+
+    let hint_sid = hint
+        .as_ref()
+        .and_then(|claims| claims.private.get("sid").and_then(Value::as_str))
+        .map(ToString::to_string);
+    let (subject, sid, mut clients) = match session {
+        Some(session) => (Some(session.subject), Some(session.sid), session.clients),
+        None => (
+            hint.as_ref()
+                .and_then(|claims| claims.registered.subject.clone()),
+            hint_sid,
+            Default::default(),
+        ),
+    };
+    clients.extend(client_id.clone());
+    let clients = clients
+        .iter()
+        .filter_map(|id| issuer.client(id))
+        .collect::<Vec<_>>();
+
+    server.audit().record(
+        AuditEvent::new(EventKind::Logout, &name, &request_id)
+            .client_id(client_id)
+            .subject(subject.clone()),
+    );
+
+    let generator =
+        JwtIdGenerator::new(issuer.key.clone(), IssuerUrl::from_url(issuer_url.clone()));
+    // a logout token identifies the session or the user, without either there's nothing to send
+    let identified = subject.is_some() || sid.is_some();
+    join_all(clients.iter().filter_map(|client| {
+        let uri = identified
+            .then_some(client.settings().backchannel_logout_uri.as_ref())
+            .flatten()?;
+        Some(backchannel_logout(
+            &server,
+            &generator,
+            client.id(),
+            uri,
+            subject.as_deref(),
+            sid.as_deref(),
+        ))
+    }))
+    .await;
+
+    let frontchannel = clients
+        .iter()
+        .filter_map(|client| client.settings().frontchannel_logout_uri.clone())
+        .map(|mut uri| {
+            uri.query_pairs_mut()
+                .append_pair("iss", issuer_url.as_str());
+            if let Some(sid) = &sid {
+                uri.query_pairs_mut().append_pair("sid", sid);
+            }
+            uri
+        })
+        .collect::<Vec<_>>();
+
+    let redirect = redirect.map(|mut uri| {
+        if let Some(state) = &request.state {
+            uri.query_pairs_mut().append_pair("state", state);
+        }
+        uri
+    });
+
+    let mut response = match redirect {
+        Some(uri) if frontchannel.is_empty() => HttpResponse::Found()
+            .append_header((header::LOCATION, uri.to_string()))
+            .finish(),
+        redirect => HttpResponse::Ok()
+            .content_type(mime::TEXT_HTML_UTF_8)
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(logged_out_page(&frontchannel, redirect.as_ref())),
+    };
+
+    clear_cookie(&mut response, &issuer_url)?;
+
+    Ok(response)
+}
+
+/// Send a logout token to the back-channel logout URI of a client
+///
+/// Failures are only logged, they must not prevent the user from logging out.
+async fn backchannel_logout(
+    server: &ApplicationState,
+    generator: &JwtIdGenerator,
+    client_id: &str,
+    uri: &Url,
+    subject: Option<&str>,
+    sid: Option<&str>,
+) {
+    let token = match generator.logout_token(client_id, subject, sid) {
+        Ok(token) => token,
+        Err(err) => {
+            log::warn!("Failed to create logout token for '{client_id}': {err}");
+            return;
+        }
+    };
+
+    let result = server
+        .http()
+        .post(uri.clone())
+        .timeout(BACKCHANNEL_TIMEOUT)
+        .form(&[("logout_token", token)])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    if let Err(err) = result {
+        log::warn!("Back-channel logout of '{client_id}' failed: {err}");
+    }
+}
+
+/// Render the page confirming the logout
+///
+/// The front-channel logout URIs are loaded in hidden iframes. Once those are loaded, the user
+/// agent is sent on to the redirect URI, if there is one.
+fn logged_out_page(frontchannel: &[Url], redirect: Option<&Url>) -> String {
+    let frames = frontchannel.iter().fold(String::new(), |mut frames, uri| {
+        let _ = writeln!(
+            frames,
+            r#"    <iframe src="{}" style="display:none"></iframe>"#,
+            escape(uri.as_str())
+        );
+        frames
+    });
+
+    let (onload, next) = match redirect {
+        Some(uri) => (
+            format!(
+                r#" data-redirect="{}" onload="window.location.replace(document.body.dataset.redirect)""#,
+                escape(uri.as_str())
+            ),
+            format!(
+                r#"    <p><a href="{}">Continue</a></p>
+"#,
+                escape(uri.as_str())
+            ),
+        ),
+        None => Default::default(),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head><title>Logged out</title></head>
+  <body{onload}>
+    <h1>You have been logged out</h1>
+{next}{frames}  </body>
+</html>
+"#
+    )
+}
@@ -1,20 +1,47 @@
+mod consent;
+mod dpop;
 mod helper;
+mod implicit;
+mod login;
+mod logout;
+mod mtls;
+mod par;
+mod request_object;
+mod session_management;
 
-use crate::{endpoints::Error, server::state::ApplicationState};
-use actix_web::http::header;
+use crate::{
+    audit::{AuditEvent, EventKind, RequestId},
+    endpoints::{Error, ErrorCode},
+    extensions::{AuthenticationData, CertificateBinding, DpopBinding},
+    issuer::{IssuerState, REQUEST_URI_PREFIX, verify},
+    oidc::Confirmation,
+    server::state::ApplicationState,
+};
 use actix_web::{
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse, Responder,
     dev::ConnectionInfo,
-    get, post,
+    get,
+    http::header,
+    middleware::from_fn,
+    post,
     web::{self, Json},
 };
+use consent::Consent;
+pub use consent::{consent_decision, list_consents, revoke_consent};
 use helper::*;
+use implicit::AuthorizationRequest;
+use login::login;
+pub use logout::{logout_get, logout_post};
 use oxide_auth::{
-    endpoint::{ClientCredentialsFlow, OwnerConsent, QueryParameter, Solicitation},
+    endpoint::{
+        ClientCredentialsFlow, Issuer, OwnerConsent, QueryParameter, Registrar, Solicitation,
+    },
     frontends::simple::endpoint::FnSolicitor,
 };
-use oxide_auth_actix::{Authorize, OAuthOperation, OAuthRequest, Refresh, Token};
-use serde::Deserialize;
+use oxide_auth_actix::{Authorize, OAuthOperation, OAuthRequest, OAuthResponse, Refresh, Token};
+pub use par::pushed_authorization_request;
+pub use session_management::check_session;
+use session_management::session_state;
 use url::Url;
 
 #[get("/{issuer}/.well-known/openid-configuration")]
@@ -32,7 +59,7 @@ pub async fn discovery(
     Ok(HttpResponse::Ok().json(issuer.discovery(base).await?))
 }
 
-fn issuer_url(
+pub(crate) fn issuer_url(
     server: &ApplicationState,
     conn: &ConnectionInfo,
     issuer: &str,
@@ -74,23 +101,285 @@ pub async fn auth_get(
     server: web::Data<ApplicationState>,
     conn: ConnectionInfo,
     path: web::Path<String>,
-    req: OAuthRequest,
-) -> Result<impl Responder, Error> {
+    request_id: RequestId,
+    http: HttpRequest,
+    mut req: OAuthRequest,
+) -> Result<HttpResponse, Error> {
     let name = path.into_inner();
 
-    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+
+    let issuer_url = issuer_url(&server, &conn, &name, [])?;
+
+    let query = |name: &str| {
+        req.query()
+            .and_then(|query| query.unique_value(name))
+            .map(|value| value.into_owned())
+    };
+    let mut pushed = None;
+    let mut parameters = None;
+    let mut object = query("request");
+    if let Some(request_uri) = query("request_uri") {
+        if request_uri.starts_with(REQUEST_URI_PREFIX) {
+            // the parameters of a pushed request replace the ones of the query
+            let request = par::resolve(&issuer, &request_uri, client_id(&req).as_deref())?;
+            object = request
+                .parameters
+                .iter()
+                .find(|(name, _)| name == "request")
+                .map(|(_, value)| value.clone());
+            parameters = Some(request.parameters);
+            pushed = Some(request_uri);
+        } else if object.is_some() {
+            return Err(Error::oauth(
+                ErrorCode::InvalidRequest,
+                "request and request_uri must not be used together",
+            ));
+        } else {
+            object = Some(
+                request_object::fetch(
+                    &server,
+                    &issuer,
+                    query("client_id").as_deref(),
+                    &request_uri,
+                )
+                .await?,
+            );
+        }
+    }
+    if let Some(object) = object {
+        let base = parameters.unwrap_or_else(|| {
+            url::form_urlencoded::parse(http.query_string().as_bytes())
+                .into_owned()
+                .collect()
+        });
+        parameters = Some(request_object::resolve(
+            &issuer,
+            &issuer_url,
+            base,
+            &object,
+        )?);
+    }
+    // the resource parameter may be repeated, which the parsed query doesn't support
+    let resources = match &parameters {
+        Some(parameters) => parameters
+            .iter()
+            .filter(|(name, _)| name == "resource")
+            .map(|(_, value)| value.clone())
+            .collect(),
+        None => url::form_urlencoded::parse(http.query_string().as_bytes())
+            .filter(|(name, _)| name == "resource")
+            .map(|(_, value)| value.into_owned())
+            .collect(),
+    };
+    if let (Some(parameters), Some(query)) = (parameters, req.query_mut()) {
+        *query = parameters.into_iter().collect();
+    }
+
+    let request = AuthorizationRequest {
+        client_id: client_id(&req),
+        request_uri: pushed,
+        resources,
+        ..AuthorizationRequest::from_query(|name| {
+            req.query()
+                .and_then(|query| query.unique_value(name))
+                .map(|value| value.into_owned())
+        })
+    };
+
+    server
+        .metrics()
+        .authorization_requests
+        .with_label_values(&[
+            &name,
+            issuer.client_label(request.client_id.as_deref().unwrap_or_default()),
+        ])
+        .inc();
+
+    let client = request
+        .client_id
+        .as_deref()
+        .and_then(|client_id| issuer.client(client_id));
+    if request.request_uri.is_none()
+        && client.is_some_and(|client| client.settings().require_pushed_authorization_requests)
+    {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequest,
+            "the client must push its authorization requests",
+        ));
+    }
+
+    if request.is_implicit() {
+        return implicit::authorize(&server, &issuer, &http, &conn, &name, &request_id, request)
+            .await;
+    }
 
     let endpoint = &mut issuer.inner.write().await.endpoint;
 
-    Ok(Authorize(req).run(with_conninfo(
-        with_solicitor(
-            endpoint,
-            FnSolicitor(move |_: &mut OAuthRequest, _: Solicitation| {
-                OwnerConsent::Authorized("Marvin".into())
-            }),
+    let response_mode = request.response_mode(&issuer.key, &issuer_url);
+    let redirect_uri = validated_redirect(
+        &endpoint.inner.registrar,
+        request.client_id.as_deref(),
+        request.redirect_uri.as_deref(),
+    );
+    let redirect_error = |err: Error| match redirect_uri.clone() {
+        Some(redirect_uri) => err.redirect(
+            redirect_uri,
+            request.state.clone(),
+            response_mode.clone().unwrap_or_default(),
         ),
-        conn,
-    )))
+        None => err,
+    };
+
+    let Some(response_mode) = response_mode.clone() else {
+        return Err(redirect_error(Error::oauth(
+            ErrorCode::InvalidRequest,
+            "unknown response mode",
+        )));
+    };
+
+    if let Err((code, description)) = request.check_resources(&issuer) {
+        return Err(redirect_error(Error::oauth(code, description)));
+    }
+
+    // only log in for valid requests, oxide-auth rejects the others before asking for consent
+    let mut login = match redirect_uri {
+        Some(_) => {
+            login(&issuer, &http, &request.login())
+                .map(Some)
+                .map_err(|(code, description)| {
+                    server.audit().record(
+                        AuditEvent::new(EventKind::Login, &name, &request_id)
+                            .client_id(request.client_id.as_deref())
+                            .failure(<&str>::from(code)),
+                    );
+                    redirect_error(Error::oauth(code, description))
+                })
+        }
+        None => Ok(None),
+    };
+    // a failed login is already recorded, and not as a failure to issue a code as well
+    let login_failed = login.is_err();
+
+    if let (Ok(Some(current)), Some(client_id)) = (&login, request.client_id.as_deref()) {
+        let scope = negotiated_scope(
+            &endpoint.inner.registrar,
+            client_id,
+            request.redirect_uri.as_deref(),
+            request.scope.as_deref(),
+        );
+        if let Some(scope) = scope {
+            match consent::check(
+                &issuer,
+                &issuer_url,
+                &http,
+                &current.session,
+                client_id,
+                &scope,
+                &request,
+            ) {
+                Ok(Consent::Granted) => {}
+                Ok(Consent::Required(mut page)) => {
+                    current.apply(&mut page, &issuer_url)?;
+                    return Ok(page);
+                }
+                Err((code, description)) => {
+                    login = Err(redirect_error(Error::oauth(code, description)));
+                }
+            }
+        }
+    }
+
+    // the pushed request was needed up to the consent of the user
+    if let Some(request_uri) = &request.request_uri {
+        issuer.pushed.remove(request_uri);
+    }
+
+    let (resp, login) = match login {
+        Err(err) => (Err(err), None),
+        Ok(login) => {
+            let subject = login.as_ref().map(|login| login.session.subject.clone());
+            let authentication = AuthenticationData {
+                auth_time: login
+                    .as_ref()
+                    .map(|login| login.session.auth_time.timestamp())
+                    .unwrap_or_default(),
+                nonce: request.nonce.clone(),
+                sid: login.as_ref().map(|login| login.session.sid.clone()),
+            };
+
+            let audit = server.audit().clone();
+            let event = AuditEvent::new(EventKind::Login, &name, &request_id);
+            let created = login.as_ref().is_some_and(|login| login.created);
+
+            let resp = Authorize(req)
+                .run(with_authentication(
+                    with_solicitor(
+                        endpoint,
+                        FnSolicitor(move |_: &mut OAuthRequest, solicitation: Solicitation| {
+                            let Some(subject) = &subject else {
+                                return OwnerConsent::Denied;
+                            };
+                            if created {
+                                let pre_grant = solicitation.pre_grant();
+                                audit.record(
+                                    event
+                                        .clone()
+                                        .client_id(Some(&pre_grant.client_id))
+                                        .subject(Some(subject))
+                                        .scopes(Some(pre_grant.scope.to_string())),
+                                );
+                            }
+                            OwnerConsent::Authorized(subject.clone())
+                        }),
+                    ),
+                    conn,
+                    authentication,
+                    request.resources.clone(),
+                ))
+                .map_err(|err| {
+                    let err = Error::from(err);
+                    match err.code() {
+                        ErrorCode::ServerError => redirect_error(err),
+                        _ => err,
+                    }
+                });
+
+            (resp, login)
+        }
+    };
+
+    if let (Ok(_), Some(login), Some(client_id)) = (&resp, &login, request.client_id.as_deref()) {
+        issuer.sessions.add_client(&login.session.id, client_id);
+    }
+
+    if !login_failed {
+        record_authorization(
+            &server,
+            &name,
+            &request_id,
+            request.client_id.as_deref(),
+            login.as_ref().map(|login| login.session.subject.as_str()),
+            request.scope.as_deref(),
+            &resp,
+        );
+    }
+
+    let session_state = match (&login, request.client_id.as_deref(), &redirect_uri) {
+        (Some(login), Some(client_id), Some(redirect_uri)) => {
+            Some(session_state(client_id, redirect_uri, &login.session.sid))
+        }
+        _ => None,
+    };
+
+    let mut response = redeliver(resp?, response_mode, session_state.as_deref(), &http);
+    if let Some(login) = login {
+        login.apply(&mut response, &issuer_url)?;
+    }
+
+    Ok(response)
 }
 
 #[get("/{issuer}/keys")]
@@ -108,51 +397,169 @@ pub async fn keys(
 #[get("/{issuer}/userinfo")]
 pub async fn userinfo_get(
     server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
     path: web::Path<String>,
-) -> Result<impl Responder, Error> {
-    userinfo(server, path).await
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    userinfo(server, conn, path, req).await
 }
 
 #[post("/{issuer}/userinfo")]
 pub async fn userinfo_post(
     server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
     path: web::Path<String>,
-) -> Result<impl Responder, Error> {
-    userinfo(server, path).await
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    userinfo(server, conn, path, req).await
 }
 
 async fn userinfo(
     server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
     path: web::Path<String>,
-) -> Result<impl Responder, Error> {
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
     let name = path.into_inner();
 
-    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
 
-    Ok(Json(issuer.userinfo()))
+    let url = issuer_url(&server, &conn, &name, ["userinfo"])?;
+    if let Err(err) = check_dpop_binding(&issuer, &req, &url) {
+        return Ok(dpop::challenge(&err));
+    }
+    if let Err(err) = mtls::check_binding(&issuer, &req) {
+        return Ok(mtls::challenge(&err));
+    }
+
+    let access_token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.split_once(' '))
+        .map(|(_, access_token)| access_token);
+    let grant = match access_token {
+        Some(access_token) => {
+            let endpoint = &issuer.inner.read().await.endpoint;
+            endpoint
+                .inner
+                .issuer
+                .recover_token(access_token)
+                .ok()
+                .flatten()
+        }
+        None => None,
+    };
+
+    let Some(grant) = grant else {
+        return Ok(mtls::challenge(&Error::oauth(
+            ErrorCode::InvalidToken,
+            "missing, unknown or expired access token",
+        )));
+    };
+
+    Ok(HttpResponse::Ok().json(issuer.userinfo(
+        &grant.owner_id,
+        Some(&grant.client_id),
+        Some(&grant.scope),
+    )))
+}
+
+/// Check that an access token bound to a DPoP key is presented with a proof for that key
+fn check_dpop_binding(issuer: &IssuerState, req: &HttpRequest, url: &Url) -> Result<(), Error> {
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidToken, description);
+
+    let Some((scheme, access_token)) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.split_once(' '))
+    else {
+        return Ok(());
+    };
+    let dpop = scheme.eq_ignore_ascii_case("DPoP");
+
+    let claims = verify(&issuer.key, access_token).ok();
+    let jkt = claims
+        .as_ref()
+        .and_then(|claims| claims.private.get("cnf")?["jkt"].as_str());
+
+    match (dpop, jkt) {
+        (false, None) => Ok(()),
+        (false, Some(_)) => Err(invalid("the access token must be presented using DPoP")),
+        (true, None) => Err(invalid("the access token is not bound to a DPoP key")),
+        (true, Some(jkt)) => {
+            let require_nonce = claims
+                .as_ref()
+                .and_then(|claims| claims.private.get("azp")?.as_str())
+                .and_then(|client_id| issuer.client(client_id))
+                .is_some_and(|client| client.settings().require_dpop_nonce);
+            match dpop::verify(issuer, req, url, Some(access_token), require_nonce)? {
+                Some(proof) if proof.jkt == jkt => Ok(()),
+                Some(_) => Err(Error::oauth(
+                    ErrorCode::InvalidDpopProof,
+                    "the access token is bound to a different key",
+                )),
+                None => Err(Error::oauth(
+                    ErrorCode::InvalidDpopProof,
+                    "missing DPoP proof",
+                )),
+            }
+        }
+    }
 }
 
-#[post("/{issuer}/refresh")]
+/// Refresh a token, the same as the `refresh_token` grant of the token endpoint
+#[post("/{issuer}/refresh", wrap = "from_fn(mtls::authenticate)")]
 pub async fn refresh(
     server: web::Data<ApplicationState>,
     conn: ConnectionInfo,
+    request_id: RequestId,
+    http: HttpRequest,
     req: OAuthRequest,
     path: web::Path<String>,
 ) -> Result<impl Responder, Error> {
     let name = path.into_inner();
 
-    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
 
-    let endpoint = &mut issuer.inner.write().await.endpoint;
+    let client_id = client_id(&req);
+
+    let resp = token_response(
+        &server,
+        &issuer,
+        &conn,
+        &name,
+        &issuer_url(&server, &conn, &name, ["refresh"])?,
+        Some("refresh_token"),
+        &http,
+        req,
+    )
+    .await;
 
-    Ok(Refresh(req).run(with_conninfo(endpoint, conn.clone()))?)
+    record_token(
+        &server,
+        &name,
+        &request_id,
+        client_id.as_deref(),
+        "refresh_token",
+        &resp,
+    );
+
+    resp
 }
 
-#[post("/{issuer}/token")]
+#[post("/{issuer}/token", wrap = "from_fn(mtls::authenticate)")]
 pub async fn token(
     server: web::Data<ApplicationState>,
     conn: ConnectionInfo,
     path: web::Path<String>,
+    request_id: RequestId,
+    http: HttpRequest,
     req: OAuthRequest,
 ) -> Result<impl Responder, Error> {
     let name = path.into_inner();
@@ -161,53 +568,2090 @@ pub async fn token(
         .issuer(&name)
         .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
 
-    let endpoint = &mut issuer.inner.write().await.endpoint;
+    let grant_type = req
+        .body()
+        .and_then(|body| body.unique_value("grant_type"))
+        .map(|grant_type| grant_type.into_owned());
+    let client_id = client_id(&req);
+
+    let resp = token_response(
+        &server,
+        &issuer,
+        &conn,
+        &name,
+        &issuer_url(&server, &conn, &name, ["token"])?,
+        grant_type.as_deref(),
+        &http,
+        req,
+    )
+    .await;
+
+    record_token(
+        &server,
+        &name,
+        &request_id,
+        client_id.as_deref(),
+        grant_type.as_deref().unwrap_or("authorization_code"),
+        &resp,
+    );
+
+    resp
+}
 
-    let grant_type = req.body().and_then(|body| body.unique_value("grant_type"));
+/// Run a grant of the token endpoint, `url` is the endpoint DPoP proofs must be made for
+#[allow(clippy::too_many_arguments)]
+async fn token_response(
+    server: &ApplicationState,
+    issuer: &IssuerState,
+    conn: &ConnectionInfo,
+    name: &str,
+    url: &Url,
+    grant_type: Option<&str>,
+    http: &HttpRequest,
+    req: OAuthRequest,
+) -> Result<OAuthResponse, Error> {
+    let settings = client_id(&req)
+        .and_then(|client_id| issuer.client(&client_id))
+        .map(|client| client.settings());
+    let proof = dpop::verify(
+        issuer,
+        http,
+        url,
+        None,
+        settings.is_some_and(|settings| settings.require_dpop_nonce),
+    )?;
+    if proof.is_none() && settings.is_some_and(|settings| settings.dpop_bound_access_tokens) {
+        return Err(Error::oauth(
+            ErrorCode::InvalidDpopProof,
+            "the client must provide a DPoP proof",
+        ));
+    }
+    let x5t = if settings.is_some_and(|s| s.tls_client_certificate_bound_access_tokens) {
+        Some(mtls::thumbprint(http).ok_or_else(|| {
+            Error::oauth(
+                ErrorCode::InvalidRequest,
+                "the client must present a TLS client certificate",
+            )
+        })?)
+    } else {
+        None
+    };
+    let confirmation = Confirmation {
+        jkt: proof.map(|proof| proof.jkt),
+        x5t_s256: x5t,
+    };
 
-    Ok(match grant_type.as_deref() {
+    if let Some(resource) = req.body().and_then(|body| body.unique_value("resource")) {
+        let allowed = client_id(&req)
+            .is_some_and(|client_id| issuer.audiences.allows_resource(&client_id, &resource));
+        if !allowed {
+            return Err(Error::oauth(
+                ErrorCode::InvalidTarget,
+                "the client may not request the resource",
+            ));
+        }
+    }
+
+    let endpoint = &mut issuer.inner.write().await.endpoint;
+
+    let resp = match grant_type {
         Some("client_credentials") => {
-            let mut flow = ClientCredentialsFlow::prepare(with_conninfo(
+            let mut flow = ClientCredentialsFlow::prepare(with_binding(
                 with_solicitor(
-                    endpoint,
+                    &mut *endpoint,
                     FnSolicitor(move |_: &mut OAuthRequest, solicitation: Solicitation| {
                         OwnerConsent::Authorized(solicitation.pre_grant().client_id.clone())
                     }),
                 ),
                 conn.clone(),
+                confirmation,
             ))?;
             flow.allow_credentials_in_body(true);
             flow.execute(req)?
         }
-        Some("refresh_token") => Refresh(req).run(with_conninfo(endpoint, conn.clone()))?,
+        Some("refresh_token") => {
+            // refreshing a bound token requires a proof for the same key, or the same certificate
+            let grant = req
+                .body()
+                .and_then(|body| body.unique_value("refresh_token"))
+                .and_then(|value| endpoint.inner.issuer.recover_refresh(&value).ok().flatten());
+            let bound = grant.as_ref().and_then(DpopBinding::from_grant);
+            if bound.is_some() && bound != confirmation.jkt {
+                return Err(Error::oauth(
+                    ErrorCode::InvalidDpopProof,
+                    "the refresh token is bound to a different key",
+                ));
+            }
+            let bound = grant.as_ref().and_then(CertificateBinding::from_grant);
+            if bound.is_some() && bound != mtls::thumbprint(http) {
+                return Err(Error::oauth(
+                    ErrorCode::InvalidGrant,
+                    "the refresh token is bound to a different certificate",
+                ));
+            }
+            Refresh(req).run(with_conninfo(&mut *endpoint, conn.clone()))?
+        }
         _ => {
-            let resp = Token(req).run(with_conninfo(endpoint, conn.clone()))?;
-            amend_id_token(resp, &server, &issuer, &conn, &name)?
+            let resp = Token(req).run(with_binding(&mut *endpoint, conn.clone(), confirmation))?;
+            amend_id_token(resp, server, issuer, &endpoint.inner.issuer, conn, name)?
         }
-    })
-}
+    };
 
-#[derive(Clone, Debug, Deserialize)]
-struct LogoutQuery {
-    pub post_logout_redirect_uri: Option<String>,
+    amend_token_type(resp, &endpoint.inner.issuer)
 }
 
-#[get("/{issuer}/logout")]
-pub async fn logout(
+/// Token revocation, RFC 7009
+#[post("/{issuer}/revoke", wrap = "from_fn(mtls::authenticate)")]
+pub async fn revoke(
     server: web::Data<ApplicationState>,
     path: web::Path<String>,
-    web::Query(LogoutQuery {
-        post_logout_redirect_uri,
-    }): web::Query<LogoutQuery>,
+    request_id: RequestId,
+    req: OAuthRequest,
 ) -> Result<impl Responder, Error> {
     let name = path.into_inner();
 
-    let _issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+
+    let event = AuditEvent::new(EventKind::Revocation, &name, &request_id);
+
+    let Some((client_id, secret)) = client_credentials(&req) else {
+        server.audit().record(event.failure("invalid_client"));
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "missing client authentication",
+        ));
+    };
+    let event = event.client_id(Some(&client_id));
+
+    let Some(value) = req
+        .body()
+        .and_then(|body| body.unique_value("token"))
+        .map(|value| value.into_owned())
+    else {
+        server.audit().record(event.failure("invalid_request"));
+        return Err(Error::oauth(ErrorCode::InvalidRequest, "missing token"));
+    };
+
+    let endpoint = &mut issuer.inner.write().await.endpoint;
+
+    if endpoint
+        .inner
+        .registrar
+        .check(&client_id, secret.as_deref().map(str::as_bytes))
+        .is_err()
+    {
+        server
+            .metrics()
+            .client_authentication_failures
+            .with_label_values(&[&name, issuer.client_label(&client_id)])
+            .inc();
+        server.audit().record(event.failure("invalid_client"));
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "client authentication failed",
+        ));
+    }
+
+    let tokens = &mut endpoint.inner.issuer;
+    let grant = tokens
+        .recover_token(&value)
+        .ok()
+        .flatten()
+        .or_else(|| tokens.recover_refresh(&value).ok().flatten());
+
+    // only the client the token was issued to may revoke it, unknown tokens are not an error
+    if let Some(grant) = grant.filter(|grant| grant.client_id == client_id) {
+        tokens.revoke(&value);
+        server.audit().record(
+            event
+                .subject(Some(grant.owner_id))
+                .scopes(Some(grant.scope.to_string())),
+        );
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        issuer::{Client, ClientSettings, Issuer, JwtIdGenerator, ProviderMetadata, RedirectUrl},
+        secrets::Key,
+        server::app::Application,
+    };
+    use actix_web::{App, http::StatusCode, http::header, test};
+    use base64::{
+        Engine,
+        prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    };
+    use openidconnect::{IssuerUrl, core::CoreGrantType, http};
+    use serde_json::Value;
+    use std::{collections::HashMap, convert::Infallible};
+    use url::Url;
+
+    #[actix_web::test]
+    async fn token_revocation() -> anyhow::Result<()> {
+        use crate::issuer::RedirectUrl;
+        use actix_web::http::StatusCode;
+        use serde_json::Value;
+        use url::Url;
+
+        let public = |id: &str| -> anyhow::Result<Client> {
+            Ok(Client::Public {
+                id: id.into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            })
+        };
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(public("frontend")?)
+            .add_client(public("other")?);
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let refresh_token = resp["refresh_token"].as_str().unwrap_or_default();
+
+        let revoke = |client_id: &str, token: &str| {
+            test::TestRequest::post()
+                .uri("/test/revoke")
+                .set_form([("client_id", client_id), ("token", token)])
+                .to_request()
+        };
+        let refresh = |token: &str| {
+            test::TestRequest::post()
+                .uri("/test/token")
+                .set_form([
+                    ("grant_type", "refresh_token"),
+                    ("client_id", "frontend"),
+                    ("refresh_token", token),
+                ])
+                .to_request()
+        };
+
+        // only the client the token was issued to can revoke it
+        let resp = test::call_service(&app, revoke("other", refresh_token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp: Value = test::call_and_read_body_json(&app, refresh(refresh_token)).await;
+        let refresh_token = resp["refresh_token"].as_str().unwrap_or_default();
+        assert!(!refresh_token.is_empty());
+
+        let resp = test::call_service(&app, revoke("frontend", refresh_token)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp: Value = test::call_and_read_body_json(&app, refresh(refresh_token)).await;
+        assert_eq!(resp["error"], "invalid_grant");
+
+        // unknown tokens are not an error, but a missing one is
+        let resp = test::call_service(&app, revoke("frontend", "unknown")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri("/test/revoke")
+            .set_form([("client_id", "frontend")])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn pkce() -> anyhow::Result<()> {
+        use crate::issuer::RedirectUrl;
+        use openidconnect::PkceCodeChallenge;
+        use serde_json::Value;
+        use url::Url;
+
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+        let authorize = |method: &str| {
+            let app = &app;
+            let uri = format!(
+                "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&response_type=code&code_challenge={}&code_challenge_method={method}",
+                challenge.as_str()
+            );
+            async move {
+                let resp =
+                    test::call_service(app, test::TestRequest::get().uri(&uri).to_request()).await;
+                let location: Url = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| location.parse().ok())?;
+                location
+                    .query_pairs()
+                    .find(|(k, _)| k == "code")
+                    .map(|(_, code)| code.into_owned())
+            }
+        };
+        let token = |code: &str, verifier: Option<&str>| {
+            let mut form = vec![
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", code),
+            ];
+            form.extend(verifier.map(|verifier| ("code_verifier", verifier)));
+            test::TestRequest::post()
+                .uri("/test/token")
+                .set_form(form)
+                .to_request()
+        };
+
+        // the code is bound to the challenge
+        let code = authorize("S256").await.unwrap_or_default();
+        let resp: Value = test::call_and_read_body_json(&app, token(&code, None)).await;
+        assert!(resp["error"].is_string());
+
+        let code = authorize("S256").await.unwrap_or_default();
+        let resp: Value = test::call_and_read_body_json(&app, token(&code, Some("wrong"))).await;
+        assert!(resp["error"].is_string());
+
+        let code = authorize("S256").await.unwrap_or_default();
+        let resp: Value =
+            test::call_and_read_body_json(&app, token(&code, Some(verifier.secret()))).await;
+        assert!(resp["access_token"].is_string());
+
+        // the plain method isn't enabled
+        assert!(authorize("plain").await.is_none());
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn discovery_round_trip() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let client = |request: http::Request<Vec<u8>>| {
+            let app = &app;
+            async move {
+                let uri = request.uri();
+                let req = test::TestRequest::get()
+                    .uri(
+                        &uri.path_and_query()
+                            .map(|p| p.to_string())
+                            .unwrap_or_default(),
+                    )
+                    .insert_header((header::HOST, uri.authority().unwrap().to_string()))
+                    .to_request();
+                let resp = test::call_service(app, req).await;
+
+                let mut response = http::Response::builder().status(resp.status().as_u16());
+                for (name, value) in resp.headers() {
+                    response = response.header(name.as_str(), value.as_bytes());
+                }
+                let body = test::read_body(resp).await;
+
+                Ok::<_, Infallible>(response.body(body.to_vec()).unwrap())
+            }
+        };
+
+        let metadata = ProviderMetadata::discover_async(
+            IssuerUrl::new("http://localhost:8080/test".into())?,
+            &client,
+        )
+        .await?;
+
+        assert_eq!(metadata.issuer().as_str(), "http://localhost:8080/test");
+        assert_eq!(
+            metadata.grant_types_supported(),
+            Some(&vec![
+                CoreGrantType::AuthorizationCode,
+                CoreGrantType::RefreshToken,
+                CoreGrantType::ClientCredentials,
+            ])
+        );
+        assert_eq!(
+            metadata
+                .additional_metadata()
+                .additional_metadata
+                .revocation_endpoint
+                .as_ref()
+                .map(|url| url.as_str()),
+            Some("http://localhost:8080/test/revoke")
+        );
+        assert_eq!(
+            metadata
+                .additional_metadata()
+                .additional_metadata
+                .code_challenge_methods_supported,
+            vec!["S256"]
+        );
+        assert_eq!(metadata.jwks().keys().len(), 1);
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn implicit_flow() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    allow_implicit: true,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let auth =
+            "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&state=xyz";
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "{auth}&response_type=id_token%20token&nonce=n-0S6"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        assert_eq!(location.query(), None);
+        let params: HashMap<_, _> =
+            url::form_urlencoded::parse(location.fragment().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+        assert_eq!(params["state"], "xyz");
+        assert_eq!(params["token_type"], "Bearer");
+        assert!(params.contains_key("access_token"));
+
+        let payload = params["id_token"].split('.').nth(1).unwrap_or_default();
+        let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload)?)?;
+        assert_eq!(claims["nonce"], "n-0S6");
+        assert_eq!(claims["aud"], serde_json::json!(["frontend"]));
+        assert!(claims["at_hash"].is_string());
+
+        // form post delivers the response as a page submitting a form
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "{auth}&response_type=code%20id_token&nonce=n-0S6&response_mode=form_post"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec())?;
+        assert!(body.contains(r#"action="http://localhost/cb""#));
+        assert!(body.contains(r#"name="code""#));
+
+        // an ID token requires a nonce
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&response_type=id_token"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location = resp.headers().get(header::LOCATION).unwrap().to_str()?;
+        assert!(location.starts_with("http://localhost/cb#error=invalid_request"));
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn login_session() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let auth = "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code&nonce=n-0S6";
+        let location =
+            |resp: &actix_web::dev::ServiceResponse| -> anyhow::Result<HashMap<String, String>> {
+                let location: Url = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .unwrap()
+                    .to_str()?
+                    .parse()?;
+                Ok(location.query_pairs().into_owned().collect())
+            };
+
+        // no session yet
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&prompt=none"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(location(&resp)?["error"], "login_required");
+
+        // log in
+        let req = test::TestRequest::get().uri(auth).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(location(&resp)?.contains_key("code"));
+        assert!(location(&resp)?.contains_key("session_state"));
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        assert_eq!(cookie.path(), Some("/test"));
+        let browser_state = resp
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "garage_door_browser_state")
+            .map(|cookie| cookie.value().to_string())
+            .unwrap_or_default();
+
+        // silent authentication
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&prompt=none"))
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let code = location(&resp)?["code"].clone();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+
+        let payload = resp["id_token"].as_str().unwrap_or_default();
+        let payload = payload.split('.').nth(1).unwrap_or_default();
+        let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload)?)?;
+        assert_eq!(claims["sub"], "Marvin");
+        assert_eq!(claims["nonce"], "n-0S6");
+        assert!(claims["auth_time"].is_number());
+        // the browser state of Session Management is the session ID
+        assert_eq!(claims["sid"], browser_state);
+
+        let req = test::TestRequest::get()
+            .uri("/test/check_session")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn remembered_consent() -> anyhow::Result<()> {
+        let mut issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "partner".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    require_consent: true,
+                    ..Default::default()
+                },
+            });
+        issuer
+            .scope_descriptions
+            .insert("openid".into(), "Your <identity>".into());
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let auth = "/test/auth?client_id=partner&redirect_uri=http://localhost/cb&scope=openid&response_type=code&state=xyz";
+
+        // ask for consent
+        let req = test::TestRequest::get().uri(auth).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec())?;
+        assert!(body.contains("Your &lt;identity&gt;"));
+        let ticket = body
+            .split(r#"name="ticket" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_string();
+
+        // approve, returning to the authorization endpoint
+        let req = test::TestRequest::post()
+            .uri("/test/consent")
+            .cookie(cookie.clone())
+            .set_form([("ticket", ticket.as_str()), ("decision", "approve")])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+
+        // the consent is remembered
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "{}?{}",
+                location.path(),
+                location.query().unwrap_or_default()
+            ))
+            .cookie(cookie.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+
+        // consents are only managed by the user they belong to
+        let req = test::TestRequest::get().uri("/test/consents").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::delete()
+            .uri("/test/consents/partner")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/test/consents")
+            .cookie(cookie.clone())
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp,
+            serde_json::json!([{"subject": "Marvin", "clientId": "partner", "scopes": ["openid"]}])
+        );
+
+        // revoked consents must be given again
+        let req = test::TestRequest::delete()
+            .uri("/test/consents/partner")
+            .cookie(cookie.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&prompt=none"))
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        assert!(
+            location
+                .query_pairs()
+                .any(|(k, v)| k == "error" && v == "consent_required")
+        );
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn denied_consent_of_pushed_request() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "partner".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    require_consent: true,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::post()
+            .uri("/test/par")
+            .set_form([
+                ("client_id", "partner"),
+                ("response_type", "code"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("scope", "openid"),
+                ("state", "xyz"),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let request_uri = resp["request_uri"].as_str().unwrap_or_default();
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/test/auth?client_id=partner&request_uri={}",
+                url::form_urlencoded::byte_serialize(request_uri.as_bytes()).collect::<String>()
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        let body = String::from_utf8(test::read_body(resp).await.to_vec())?;
+        let ticket = body
+            .split(r#"name="ticket" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_string();
+
+        // the denial is delivered to the client, even though the query only refers to the request
+        let req = test::TestRequest::post()
+            .uri("/test/consent")
+            .cookie(cookie)
+            .set_form([("ticket", ticket.as_str()), ("decision", "deny")])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        assert_eq!(location.path(), "/cb");
+        let query = location
+            .query_pairs()
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(query["error"], "access_denied");
+        assert_eq!(query["state"], "xyz");
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn logout() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    allow_implicit: true,
+                    post_logout_redirect_urls: vec!["http://localhost/logged-out".parse()?],
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=id_token&nonce=n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let id_token =
+            url::form_urlencoded::parse(location.fragment().unwrap_or_default().as_bytes())
+                .find(|(k, _)| k == "id_token")
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+
+        // not registered for the client
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/test/logout?id_token_hint={id_token}&post_logout_redirect_uri=http://evil.example"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/test/logout?id_token_hint={id_token}&post_logout_redirect_uri=http://localhost/logged-out&state=abc"
+            ))
+            .cookie(cookie.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "http://localhost/logged-out?state=abc"
+        );
+
+        // the session is gone
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=id_token&nonce=n&prompt=none")
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location = resp.headers().get(header::LOCATION).unwrap().to_str()?;
+        assert!(location.contains("error=login_required"));
+
+        let req = test::TestRequest::get().uri("/test/logout").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // a hint without azp, identifying the client by its audience
+        let generator = JwtIdGenerator::new(
+            Key::new("key1", "this-is-not-a-secret-test-key-0123456789"),
+            IssuerUrl::new("http://localhost:8080/test".into())?,
+        );
+        let id_token = generator.create(chrono::Duration::minutes(5))?;
+        let req = test::TestRequest::get()
+            .uri(&format!("/test/logout?id_token_hint={id_token}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    /// Accept a single HTTP request, returning its body
+    fn receive_once() -> anyhow::Result<(Url, std::sync::mpsc::Receiver<String>)> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/backchannel", listener.local_addr()?).parse()?;
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let Ok((mut stream, _)) = listener.accept() else {
+                return;
+            };
+            let mut data = vec![];
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                data.extend_from_slice(&buf[..n]);
+                let request = String::from_utf8_lossy(&data);
+                if let Some((head, body)) = request.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or_default();
+                    if body.len() >= length {
+                        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+                        let _ = tx.send(body.to_string());
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok((url, rx))
+    }
+
+    #[actix_web::test]
+    async fn backchannel_frontchannel_logout() -> anyhow::Result<()> {
+        let (backchannel, received) = receive_once()?;
+
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    allow_implicit: true,
+                    post_logout_redirect_urls: vec!["http://localhost/logged-out".parse()?],
+                    backchannel_logout_uri: Some(backchannel),
+                    frontchannel_logout_uri: Some("http://localhost/frontchannel".parse()?),
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=id_token&nonce=n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let id_token =
+            url::form_urlencoded::parse(location.fragment().unwrap_or_default().as_bytes())
+                .find(|(k, _)| k == "id_token")
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+        let payload = |token: &str| -> anyhow::Result<Value> {
+            let payload = token.split('.').nth(1).unwrap_or_default();
+            Ok(serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+            )?)
+        };
+        let sid = payload(&id_token)?["sid"].as_str().unwrap().to_string();
+
+        // without a session or a hint, there's no one to log out at the client
+        let req = test::TestRequest::get()
+            .uri("/test/logout?client_id=frontend")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(received.try_recv().is_err());
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/test/logout?id_token_hint={id_token}&post_logout_redirect_uri=http://localhost/logged-out"
+            ))
+            .cookie(cookie)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec())?;
+        assert!(body.contains(&format!(
+            r#"<iframe src="http://localhost/frontchannel?iss=http%3A%2F%2Flocalhost%3A8080%2Ftest&amp;sid={sid}""#
+        )));
+        assert!(body.contains(r#"data-redirect="http://localhost/logged-out""#));
+
+        let body = received.recv_timeout(std::time::Duration::from_secs(5))?;
+        let token = url::form_urlencoded::parse(body.as_bytes())
+            .find(|(k, _)| k == "logout_token")
+            .map(|(_, v)| v.into_owned())
+            .unwrap_or_default();
+        let claims = payload(&token)?;
+        assert_eq!(claims["iss"], "http://localhost:8080/test");
+        assert_eq!(claims["aud"], "frontend");
+        assert_eq!(claims["sub"], "Marvin");
+        assert_eq!(claims["sid"], sid);
+        assert!(claims["events"]["http://schemas.openid.net/event/backchannel-logout"].is_object());
+        assert!(claims.get("nonce").is_none());
+        assert!(claims["exp"].as_i64() > claims["iat"].as_i64());
+        let header: Value = serde_json::from_slice(
+            &BASE64_URL_SAFE_NO_PAD.decode(token.split('.').next().unwrap_or_default())?,
+        )?;
+        assert_eq!(header["typ"], "logout+jwt");
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn pushed_authorization_request() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    require_pushed_authorization_requests: true,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("client:secret"));
+        let params = [
+            ("response_type", "code"),
+            ("redirect_uri", "http://localhost/"),
+            ("scope", "openid"),
+            ("state", "xyz"),
+        ];
+
+        // the client must authenticate
+        let req = test::TestRequest::post()
+            .uri("/test/par")
+            .append_header((header::AUTHORIZATION, "Basic Y2xpZW50Ondyb25n"))
+            .set_form(params)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/test/par")
+            .append_header((header::AUTHORIZATION, basic.as_str()))
+            .set_form(params)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let resp: Value = test::read_body_json(resp).await;
+        let request_uri = resp["request_uri"].as_str().unwrap_or_default().to_string();
+        assert!(request_uri.starts_with("urn:ietf:params:oauth:request_uri:"));
+        assert_eq!(resp["expires_in"], 60);
+
+        // the request is validated before it is stored
+        for (name, value, error) in [
+            ("redirect_uri", "http://elsewhere/", "invalid_request"),
+            ("response_type", "device_code", "unsupported_response_type"),
+            ("response_type", "code id_token", "unauthorized_client"),
+            ("response_mode", "telepathy", "invalid_request"),
+            ("scope", "openid admin", "invalid_scope"),
+            ("code_challenge_method", "plain", "invalid_request"),
+        ] {
+            let mut params = params
+                .iter()
+                .filter(|(k, _)| *k != name)
+                .copied()
+                .collect::<Vec<_>>();
+            params.extend([(name, value), ("code_challenge", "challenge")]);
+            let req = test::TestRequest::post()
+                .uri("/test/par")
+                .append_header((header::AUTHORIZATION, basic.as_str()))
+                .set_form(params)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{name}={value}");
+            let resp: Value = test::read_body_json(resp).await;
+            assert_eq!(resp["error"], error, "{name}={value}");
+        }
+
+        let auth = format!(
+            "/test/auth?client_id=client&request_uri={}",
+            url::form_urlencoded::byte_serialize(request_uri.as_bytes()).collect::<String>()
+        );
+        let req = test::TestRequest::get().uri(&auth).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let query = location
+            .query_pairs()
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        assert!(query.contains_key("code"));
+        assert_eq!(query["state"], "xyz");
+
+        // a pushed request can only be used once
+        let req = test::TestRequest::get().uri(&auth).to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_uri");
+
+        // the client is required to push its requests
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=client&response_type=code&redirect_uri=http://localhost/&scope=openid")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    /// Answer a single HTTP request, with the body sent once it is known
+    fn serve_once() -> anyhow::Result<(Url, std::sync::mpsc::Sender<String>)> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/request.jwt", listener.local_addr()?).parse()?;
+        let (tx, rx) = std::sync::mpsc::channel::<String>();
+
+        std::thread::spawn(move || {
+            let Ok(body) = rx.recv() else {
+                return;
+            };
+            let Ok((mut stream, _)) = listener.accept() else {
+                return;
+            };
+            let mut data = vec![];
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                data.extend_from_slice(&buf[..n]);
+                if data.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let _ = stream.write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                            body.len()
+                        )
+                        .as_bytes(),
+                    );
+                    return;
+                }
+            }
+        });
+
+        Ok((url, tx))
+    }
+
+    #[actix_web::test]
+    async fn signed_request_object() -> anyhow::Result<()> {
+        use biscuit::{
+            ClaimsSet, Empty, RegisteredClaims, SingleOrMultiple,
+            jwa::SignatureAlgorithm,
+            jws::{Compact, RegisteredHeader, Secret},
+        };
+
+        let key = "this-is-the-key-of-the-client-0123456789";
+        let (object_url, object) = serve_once()?;
+        let (large_url, large) = serve_once()?;
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    jwks: Some(serde_json::from_value(serde_json::json!({
+                        "keys": [{"kty": "oct", "k": BASE64_URL_SAFE_NO_PAD.encode(key)}]
+                    }))?),
+                    request_uris: vec![object_url.clone(), large_url.clone()],
+                    ..Default::default()
+                },
+            })
+            .add_client(Client::Public {
+                id: "keyless".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    request_uris: vec![object_url.clone()],
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let sign_for = |key: &str,
+                        algorithm: SignatureAlgorithm,
+                        redirect_uri: &str|
+         -> anyhow::Result<String> {
+            let claims = ClaimsSet {
+                registered: RegisteredClaims {
+                    issuer: Some("frontend".into()),
+                    audience: Some(SingleOrMultiple::Single(
+                        "http://localhost:8080/test".into(),
+                    )),
+                    ..Default::default()
+                },
+                private: serde_json::json!({
+                    "client_id": "frontend",
+                    "response_type": "code",
+                    "redirect_uri": redirect_uri,
+                    "state": "from-object",
+                    "max_age": 300,
+                }),
+            };
+            let jwt = Compact::<_, Empty>::new_decoded(
+                From::from(RegisteredHeader {
+                    algorithm,
+                    ..Default::default()
+                }),
+                claims,
+            );
+            let secret = match algorithm {
+                SignatureAlgorithm::None => Secret::None,
+                _ => Secret::Bytes(key.as_bytes().to_vec()),
+            };
+            Ok(jwt.into_encoded(&secret)?.encoded()?.to_string())
+        };
+        let sign = |key: &str, algorithm: SignatureAlgorithm| {
+            sign_for(key, algorithm, "http://localhost/cb")
+        };
+        let auth = |object: &str| {
+            format!("/test/auth?client_id=frontend&scope=openid&state=from-query&request={object}")
+        };
+
+        let req = test::TestRequest::get()
+            .uri(&auth(&sign(key, SignatureAlgorithm::HS256)?))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let query = location
+            .query_pairs()
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        assert!(query.contains_key("code"));
+        // the parameters of the object take precedence
+        assert_eq!(query["state"], "from-object");
+
+        // signed with a different key
+        let req = test::TestRequest::get()
+            .uri(&auth(&sign(
+                "this-is-not-the-key-of-the-client-0123456789",
+                SignatureAlgorithm::HS256,
+            )?))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_object");
+
+        // not signed at all
+        let req = test::TestRequest::get()
+            .uri(&auth(&sign(key, SignatureAlgorithm::None)?))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_object");
+
+        // only registered URIs are fetched, and only for clients with keys
+        let by_reference = |client_id: &str, url: &Url| {
+            format!(
+                "/test/auth?client_id={client_id}&scope=openid&request_uri={}",
+                url::form_urlencoded::byte_serialize(url.as_str().as_bytes()).collect::<String>()
+            )
+        };
+        let req = test::TestRequest::get()
+            .uri(&by_reference(
+                "frontend",
+                &"http://127.0.0.1:1/request.jwt".parse()?,
+            ))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_uri");
+        assert_eq!(
+            resp["error_description"],
+            "request_uri is not registered for the client"
+        );
+        let req = test::TestRequest::get()
+            .uri(&by_reference("keyless", &object_url))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_object");
+
+        object.send(sign(key, SignatureAlgorithm::HS256)?)?;
+        let req = test::TestRequest::get()
+            .uri(&by_reference("frontend", &object_url))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+
+        large.send("a".repeat(100 * 1024))?;
+        let req = test::TestRequest::get()
+            .uri(&by_reference("frontend", &large_url))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_uri");
+        assert_eq!(resp["error_description"], "request object is too large");
+
+        // a pushed request object is verified when it is pushed
+        let push = |object: String| {
+            test::TestRequest::post()
+                .uri("/test/par")
+                .set_form([("client_id", "frontend".to_string()), ("request", object)])
+                .to_request()
+        };
+        let req = push(sign(
+            "this-is-not-the-key-of-the-client-0123456789",
+            SignatureAlgorithm::HS256,
+        )?);
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request_object");
+
+        let req = push(sign_for(
+            key,
+            SignatureAlgorithm::HS256,
+            "http://elsewhere/cb",
+        )?);
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["error"], "invalid_request");
+        assert_eq!(resp["error_description"], "invalid redirect URI");
+
+        let req = push(sign(key, SignatureAlgorithm::HS256)?);
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn jwt_secured_authorization_response() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let auth =
+            "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&state=xyz";
+        let payload = |token: &str| -> anyhow::Result<Value> {
+            let payload = token.split('.').nth(1).unwrap_or_default();
+            Ok(serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+            )?)
+        };
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&response_type=code&response_mode=jwt"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let query = location
+            .query_pairs()
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(query.keys().collect::<Vec<_>>(), vec!["response"]);
+        let claims = payload(&query["response"])?;
+        assert_eq!(claims["iss"], "http://localhost:8080/test");
+        assert_eq!(claims["aud"], "frontend");
+        assert_eq!(claims["state"], "xyz");
+        assert!(claims["code"].is_string());
+        assert!(claims["exp"].is_number());
+
+        // errors are wrapped as well
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "{auth}&response_type=token&response_mode=fragment.jwt"
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let response =
+            url::form_urlencoded::parse(location.fragment().unwrap_or_default().as_bytes())
+                .find(|(k, _)| k == "response")
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+        let claims = payload(&response)?;
+        assert_eq!(claims["error"], "unauthorized_client");
+        assert_eq!(claims["state"], "xyz");
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn unbound_bearer_token() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .append_header((
+                header::AUTHORIZATION,
+                format!("Basic {}", BASE64_STANDARD.encode("client:secret")),
+            ))
+            .set_form([("grant_type", "client_credentials")])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["token_type"], "bearer");
+        let access_token = resp["access_token"].as_str().unwrap_or_default();
+
+        let userinfo = |scheme: &str| {
+            test::TestRequest::get()
+                .uri("/test/userinfo")
+                .append_header((header::AUTHORIZATION, format!("{scheme} {access_token}")))
+                .to_request()
+        };
+        let resp = test::call_service(&app, userinfo("Bearer")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // an unbound token can't be presented as a DPoP token
+        let resp = test::call_service(&app, userinfo("DPoP")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    /// Create a DPoP key, returning a function which signs proofs with it
+    fn dpop_prover() -> anyhow::Result<impl Fn(&str, &str, Value) -> anyhow::Result<String>> {
+        use biscuit::{
+            ClaimsSet, Empty, RegisteredClaims,
+            jwa::SignatureAlgorithm,
+            jws::{Compact, RegisteredHeader, Secret},
+        };
+        use ring::{
+            rand::SystemRandom,
+            signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair},
+        };
+        use std::sync::Arc;
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|err| anyhow::anyhow!("{err}"))?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .map_err(|err| anyhow::anyhow!("{err}"))?;
+        let point = key.public_key().as_ref();
+        let jwk: biscuit::jwk::JWK<Empty> = serde_json::from_value(serde_json::json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": BASE64_URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": BASE64_URL_SAFE_NO_PAD.encode(&point[33..]),
+        }))?;
+        let secret = Secret::EcdsaKeyPair(Arc::new(key));
+
+        Ok(
+            move |htm: &str, htu: &str, extra: Value| -> anyhow::Result<String> {
+                let mut private = serde_json::json!({"htm": htm, "htu": htu});
+                private
+                    .as_object_mut()
+                    .unwrap()
+                    .extend(extra.as_object().cloned().unwrap_or_default());
+                let jwt = Compact::<_, Empty>::new_decoded(
+                    From::from(RegisteredHeader {
+                        algorithm: SignatureAlgorithm::ES256,
+                        media_type: Some("dpop+jwt".into()),
+                        web_key: Some(Clone::clone(&jwk)),
+                        ..Default::default()
+                    }),
+                    ClaimsSet {
+                        registered: RegisteredClaims {
+                            id: Some(uuid::Uuid::new_v4().to_string()),
+                            issued_at: Some(chrono::Utc::now().into()),
+                            ..Default::default()
+                        },
+                        private,
+                    },
+                );
+                Ok(jwt.into_encoded(&secret)?.encoded()?.to_string())
+            },
+        )
+    }
+
+    #[actix_web::test]
+    async fn dpop_bound_access_token() -> anyhow::Result<()> {
+        use sha2::{Digest, Sha256};
+
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    dpop_bound_access_tokens: true,
+                    require_dpop_nonce: true,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let proof = dpop_prover()?;
+        let token_request = |proof: Option<String>| {
+            let mut req = test::TestRequest::post()
+                .uri("/test/token")
+                .append_header((
+                    header::AUTHORIZATION,
+                    format!("Basic {}", BASE64_STANDARD.encode("client:secret")),
+                ))
+                .set_form([("grant_type", "client_credentials")]);
+            if let Some(proof) = proof {
+                req = req.append_header(("DPoP", proof));
+            }
+            req.to_request()
+        };
+        let token_url = "http://localhost:8080/test/token";
+
+        // the client must use DPoP
+        let resp: Value = test::call_and_read_body_json(&app, token_request(None)).await;
+        assert_eq!(resp["error"], "invalid_dpop_proof");
+
+        // and must use a nonce
+        let resp = test::call_service(
+            &app,
+            token_request(Some(proof("POST", token_url, Value::Null)?)),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let nonce = resp
+            .headers()
+            .get("DPoP-Nonce")
+            .unwrap()
+            .to_str()?
+            .to_string();
+        let resp: Value = test::read_body_json(resp).await;
+        assert_eq!(resp["error"], "use_dpop_nonce");
+
+        let resp: Value = test::call_and_read_body_json(
+            &app,
+            token_request(Some(proof(
+                "POST",
+                token_url,
+                serde_json::json!({"nonce": nonce}),
+            )?)),
+        )
+        .await;
+        assert_eq!(resp["token_type"], "DPoP");
+        let access_token = resp["access_token"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let payload = access_token.split('.').nth(1).unwrap_or_default();
+        let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload)?)?;
+        assert!(claims["cnf"]["jkt"].is_string());
+
+        // a bound token can't be used as a bearer token
+        let req = test::TestRequest::get()
+            .uri("/test/userinfo")
+            .append_header((header::AUTHORIZATION, format!("Bearer {access_token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            resp.headers()
+                .get(header::WWW_AUTHENTICATE)
+                .unwrap()
+                .to_str()?
+                .starts_with(r#"DPoP error="invalid_token""#)
+        );
+
+        let ath = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(&access_token));
+        let proof = proof(
+            "GET",
+            "http://localhost:8080/test/userinfo",
+            serde_json::json!({"nonce": nonce, "ath": ath}),
+        )?;
+        let userinfo = || {
+            test::TestRequest::get()
+                .uri("/test/userinfo")
+                .append_header((header::AUTHORIZATION, format!("DPoP {access_token}")))
+                .append_header(("DPoP", proof.as_str()))
+                .to_request()
+        };
+        let resp = test::call_service(&app, userinfo()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // proofs must not be replayed
+        let resp = test::call_service(&app, userinfo()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn dpop_bound_refresh_token() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    dpop_bound_access_tokens: true,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+        let proof = dpop_prover()?;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .append_header((
+                "DPoP",
+                proof("POST", "http://localhost:8080/test/token", Value::Null)?,
+            ))
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["token_type"], "DPoP");
+        let refresh_token = resp["refresh_token"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        let refresh = |proof: Option<String>| {
+            let mut req = test::TestRequest::post().uri("/test/refresh").set_form([
+                ("grant_type", "refresh_token"),
+                ("client_id", "frontend"),
+                ("refresh_token", &refresh_token),
+            ]);
+            if let Some(proof) = proof {
+                req = req.append_header(("DPoP", proof));
+            }
+            req.to_request()
+        };
+
+        // the legacy refresh endpoint requires a proof as well
+        let resp: Value = test::call_and_read_body_json(&app, refresh(None)).await;
+        assert_eq!(resp["error"], "invalid_dpop_proof");
+        // made for the endpoint it is sent to
+        let resp: Value = test::call_and_read_body_json(
+            &app,
+            refresh(Some(proof(
+                "POST",
+                "http://localhost:8080/test/token",
+                Value::Null,
+            )?)),
+        )
+        .await;
+        assert_eq!(resp["error"], "invalid_dpop_proof");
+
+        let resp: Value = test::call_and_read_body_json(
+            &app,
+            refresh(Some(proof(
+                "POST",
+                "http://localhost:8080/test/refresh",
+                Value::Null,
+            )?)),
+        )
+        .await;
+        assert_eq!(resp["token_type"], "DPoP");
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn token_lifetimes() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .lifetimes(serde_json::from_value(serde_json::json!({
+                "idToken": 30,
+                "refreshToken": 60,
+            }))?)
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    lifetimes: serde_json::from_value(serde_json::json!({"accessToken": 1}))?,
+                    ..Default::default()
+                },
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(resp["expires_in"].as_i64().unwrap_or_default() <= 1);
+
+        let claims = |token: &Value| -> anyhow::Result<Value> {
+            let payload = token.as_str().unwrap_or_default();
+            let payload = payload.split('.').nth(1).unwrap_or_default();
+            Ok(serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+            )?)
+        };
+        let lifetime = |claims: Value| {
+            claims["exp"].as_i64().unwrap_or_default() - claims["iat"].as_i64().unwrap_or_default()
+        };
+        assert!((0..=1).contains(&lifetime(claims(&resp["access_token"])?)));
+        assert!((29..=30).contains(&lifetime(claims(&resp["id_token"])?)));
+        let refresh = claims(&resp["refresh_token"])?;
+        let expiry = refresh["exp"].as_i64().unwrap_or_default() - chrono::Utc::now().timestamp();
+        assert!((59..=60).contains(&expiry));
+
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+        // the access token expired, but the refresh token is still valid
+        let req = test::TestRequest::post()
+            .uri("/test/refresh")
+            .set_form([
+                ("grant_type", "refresh_token"),
+                (
+                    "refresh_token",
+                    resp["refresh_token"].as_str().unwrap_or_default(),
+                ),
+            ])
+            .to_request();
+        let refreshed = test::call_service(&app, req).await;
+        assert_eq!(refreshed.status(), StatusCode::OK);
+        let refreshed: Value = test::read_body_json(refreshed).await;
+        assert!(refreshed["access_token"].is_string());
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn resource_indicators() -> anyhow::Result<()> {
+        let resources = vec!["https://api.example.com".to_string()];
+        let mut issuer = Issuer::new(
+            "this-is-not-a-secret-test-key-0123456789",
+            ["openid", "read"],
+        )?
+        .add_client(Client::Confidential {
+            id: "client".into(),
+            secret: "secret".to_string().into(),
+            default_scope: "openid read".into(),
+            settings: ClientSettings {
+                audiences: vec!["api".into()],
+                resources: resources.clone(),
+                ..Default::default()
+            },
+        })
+        .add_client(Client::Public {
+            id: "frontend".into(),
+            redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+            default_scope: "openid".into(),
+            settings: ClientSettings {
+                resources,
+                ..Default::default()
+            },
+        });
+        issuer
+            .scope_audiences
+            .insert("read".into(), vec!["read-api".into()]);
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let audience = |resp: &Value| -> anyhow::Result<Value> {
+            let payload = resp["access_token"].as_str().unwrap_or_default();
+            let payload = payload.split('.').nth(1).unwrap_or_default();
+            let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload)?)?;
+            Ok(claims["aud"].clone())
+        };
+        let client_credentials = |resource: Option<&str>| {
+            let mut form = vec![("grant_type", "client_credentials")];
+            form.extend(resource.map(|resource| ("resource", resource)));
+            test::TestRequest::post()
+                .uri("/test/token")
+                .append_header((
+                    header::AUTHORIZATION,
+                    format!("Basic {}", BASE64_STANDARD.encode("client:secret")),
+                ))
+                .set_form(form)
+                .to_request()
+        };
+
+        // the default audiences of the client and the scopes
+        let resp: Value = test::call_and_read_body_json(&app, client_credentials(None)).await;
+        assert_eq!(audience(&resp)?, serde_json::json!(["api", "read-api"]));
+
+        let resp: Value = test::call_and_read_body_json(
+            &app,
+            client_credentials(Some("https://api.example.com")),
+        )
+        .await;
+        assert_eq!(audience(&resp)?, "https://api.example.com");
+
+        let resp =
+            test::call_service(&app, client_credentials(Some("https://other.example.com"))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp: Value = test::read_body_json(resp).await;
+        assert_eq!(resp["error"], "invalid_target");
+
+        // resources requested by the authorization request end up in the token
+        let auth = "/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code";
+        let query =
+            |resp: &actix_web::dev::ServiceResponse| -> anyhow::Result<HashMap<String, String>> {
+                let location: Url = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .unwrap()
+                    .to_str()?
+                    .parse()?;
+                Ok(location.query_pairs().into_owned().collect())
+            };
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&resource=https://other.example.com"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(query(&resp)?["error"], "invalid_target");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("{auth}&resource=https://api.example.com"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let code = query(&resp)?["code"].clone();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(audience(&resp)?, "https://api.example.com");
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn claim_mapping() -> anyhow::Result<()> {
+        let mut issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .users(serde_json::from_value::<Vec<_>>(serde_json::json!([{
+                "id": "Marvin",
+                "attributes": {"email": "marvin@example.com", "roles": ["admin"]},
+            }]))?)
+            .add_client(Client::Public {
+                id: "frontend".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        issuer.claims = serde_json::from_value(serde_json::json!([
+            {"name": "email", "value": {"attribute": "email"}},
+            {"name": "realm_access.roles", "value": {"attribute": "roles"}, "targets": ["accessToken"]},
+            {"name": "tenant", "value": {"static": "acme"}, "targets": ["idToken", "userinfo"]},
+        ]))?;
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=frontend&redirect_uri=http://localhost/cb&scope=openid&response_type=code")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let location: Url = resp
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/test/token")
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("client_id", "frontend"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+
+        let claims = |token: &Value| -> anyhow::Result<Value> {
+            let payload = token.as_str().unwrap_or_default();
+            let payload = payload.split('.').nth(1).unwrap_or_default();
+            Ok(serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+            )?)
+        };
+        let access_token = claims(&resp["access_token"])?;
+        assert_eq!(access_token["email"], "marvin@example.com");
+        assert_eq!(
+            access_token["realm_access"]["roles"],
+            serde_json::json!(["admin"])
+        );
+        assert!(access_token.get("tenant").is_none());
+
+        let id_token = claims(&resp["id_token"])?;
+        assert_eq!(id_token["email"], "marvin@example.com");
+        assert_eq!(id_token["tenant"], "acme");
+        assert!(id_token.get("realm_access").is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/test/userinfo")
+            .append_header((
+                header::AUTHORIZATION,
+                format!(
+                    "Bearer {}",
+                    resp["access_token"].as_str().unwrap_or_default()
+                ),
+            ))
+            .to_request();
+        let userinfo: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(userinfo["sub"], "Marvin");
+        assert_eq!(userinfo["email"], "marvin@example.com");
+        assert_eq!(userinfo["tenant"], "acme");
+
+        // without a valid access token, no claims are returned
+        for authorization in [None, Some("Bearer not-a-token")] {
+            let mut req = test::TestRequest::get().uri("/test/userinfo");
+            if let Some(authorization) = authorization {
+                req = req.append_header((header::AUTHORIZATION, authorization));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert!(
+                resp.headers()
+                    .get(header::WWW_AUTHENTICATE)
+                    .unwrap()
+                    .to_str()?
+                    .starts_with(r#"Bearer error="invalid_token""#)
+            );
+            assert!(test::read_body(resp).await.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn group_and_role_claims() -> anyhow::Result<()> {
+        let settings = ClientSettings {
+            roles: vec!["admin".into(), "user".into()],
+            ..Default::default()
+        };
+        let mut issuer =
+            Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid", "roles"])?
+                .groups(serde_json::from_value::<Vec<_>>(serde_json::json!([
+                    {"id": "admins", "groups": ["staff"], "roles": {"frontend": ["admin"], "backend": ["admin"]}},
+                    {"id": "staff", "roles": {"frontend": ["user"], "backend": ["user"]}},
+                ]))?)
+                .users(serde_json::from_value::<Vec<_>>(serde_json::json!([
+                    {"id": "Marvin", "groups": ["admins"]},
+                ]))?)
+                .add_client(Client::Public {
+                    id: "frontend".into(),
+                    redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                    default_scope: "openid roles".into(),
+                    settings: settings.clone(),
+                })
+                .add_client(Client::Public {
+                    id: "backend".into(),
+                    redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                    default_scope: "openid".into(),
+                    settings,
+                });
+        issuer.claims = serde_json::from_value(serde_json::json!([
+            {"name": "groups", "value": "groups"},
+            {"name": "roles", "value": "roles", "scope": "roles"},
+        ]))?;
+        assert!(issuer.validate("test").is_empty());
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost:8080".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let claims = |token: &Value| -> anyhow::Result<Value> {
+            let payload = token.as_str().unwrap_or_default();
+            let payload = payload.split('.').nth(1).unwrap_or_default();
+            Ok(serde_json::from_slice(
+                &BASE64_URL_SAFE_NO_PAD.decode(payload)?,
+            )?)
+        };
+
+        // the roles are only added for the client granted the roles scope
+        for (client_id, roles) in [
+            ("frontend", Some(serde_json::json!(["admin", "user"]))),
+            ("backend", None),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/test/auth?client_id={client_id}&redirect_uri=http://localhost/cb&response_type=code"))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            let location: Url = resp
+                .headers()
+                .get(header::LOCATION)
+                .unwrap()
+                .to_str()?
+                .parse()?;
+            let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+
+            let req = test::TestRequest::post()
+                .uri("/test/token")
+                .set_form([
+                    ("grant_type", "authorization_code"),
+                    ("client_id", client_id),
+                    ("redirect_uri", "http://localhost/cb"),
+                    ("code", &code),
+                ])
+                .to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+
+            let req = test::TestRequest::get()
+                .uri("/test/userinfo")
+                .append_header((
+                    header::AUTHORIZATION,
+                    format!(
+                        "Bearer {}",
+                        resp["access_token"].as_str().unwrap_or_default()
+                    ),
+                ))
+                .to_request();
+            let userinfo: Value = test::call_and_read_body_json(&app, req).await;
+
+            for token in [
+                claims(&resp["access_token"])?,
+                claims(&resp["id_token"])?,
+                userinfo,
+            ] {
+                assert_eq!(token["groups"], serde_json::json!(["admins", "staff"]));
+                assert_eq!(token.get("roles"), roles.as_ref());
+            }
+        }
+
+        // the groups of the user are only disclosed to a client holding an access token
+        let req = test::TestRequest::get().uri("/test/userinfo").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(test::read_body(resp).await.is_empty());
 
-    match post_logout_redirect_uri {
-        Some(uri) => Ok(HttpResponse::TemporaryRedirect()
-            .append_header((header::LOCATION, uri))
-            .finish()),
-        None => Ok(HttpResponse::NoContent().finish()),
+        Ok(())
     }
 }
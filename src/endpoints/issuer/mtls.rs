@@ -0,0 +1,164 @@
+//! Mutual TLS client authentication and certificate-bound access tokens (RFC 8705)
+
+use super::helper::basic_credentials;
+use crate::{
+    endpoints::{Error, ErrorCode},
+    issuer::{Client, IssuerState, certificate_thumbprint, verify},
+    server::{state::ApplicationState, tls::ClientCertificate},
+};
+use actix_web::{
+    HttpRequest, HttpResponse,
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web,
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use url::form_urlencoded;
+
+/// Authenticate clients using their TLS client certificate
+///
+/// oxide-auth only knows about client secrets, so once the certificate of a client checks out,
+/// the request is authenticated with the secret of the client on its behalf. The client must not
+/// use its secret itself.
+pub async fn authenticate(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(server) = req.app_data::<web::Data<ApplicationState>>().cloned() else {
+        return next.call(req).await;
+    };
+    let Some(name) = req.match_info().get("issuer").map(ToString::to_string) else {
+        return next.call(req).await;
+    };
+    let Some(issuer) = server.issuer(&name) else {
+        return next.call(req).await;
+    };
+
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(Payload::from(body.clone()));
+
+    let parameters = form_urlencoded::parse(&body)
+        .into_owned()
+        .collect::<Vec<_>>();
+    let parameter = |name: &str| parameters.iter().find(|(k, _)| k == name);
+
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(basic_credentials);
+    let client_id = basic
+        .as_ref()
+        .map(|(client_id, _)| client_id)
+        .or_else(|| parameter("client_id").map(|(_, client_id)| client_id));
+
+    let Some(Client::Confidential {
+        id,
+        secret,
+        settings,
+        ..
+    }) = client_id.and_then(|client_id| issuer.client(client_id))
+    else {
+        return next.call(req).await;
+    };
+    let Some(tls_client_auth) = &settings.tls_client_auth else {
+        return next.call(req).await;
+    };
+
+    if basic.is_some() || parameter("client_secret").is_some() {
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "the client must authenticate using its certificate",
+        )
+        .into());
+    }
+
+    let authenticated = req
+        .conn_data::<ClientCertificate>()
+        .is_some_and(|certificate| {
+            tls_client_auth.authenticates(
+                &certificate.certificate,
+                certificate.trusted,
+                settings.jwks.as_ref(),
+            )
+        });
+    if !authenticated {
+        server
+            .metrics()
+            .client_authentication_failures
+            .with_label_values(&[&name, id])
+            .inc();
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "client certificate authentication failed",
+        )
+        .into());
+    }
+
+    // the client ID moves to the authorization header, oxide-auth rejects having it twice
+    let body = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(parameters.iter().filter(|(k, _)| k != "client_id"))
+        .finish();
+    req.set_payload(Payload::from(web::Bytes::from(body)));
+
+    let credentials = BASE64_STANDARD.encode(format!("{id}:{}", secret.as_str()));
+    req.headers_mut().insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Basic {credentials}"))?,
+    );
+
+    next.call(req).await
+}
+
+/// The thumbprint of the certificate the client presented, if any
+pub fn thumbprint(req: &HttpRequest) -> Option<String> {
+    req.conn_data::<ClientCertificate>()
+        .map(|certificate| certificate_thumbprint(&certificate.certificate))
+}
+
+/// Check that an access token bound to a certificate is presented along with that certificate
+pub fn check_binding(issuer: &IssuerState, req: &HttpRequest) -> Result<(), Error> {
+    let Some(access_token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.split_once(' '))
+        .map(|(_, access_token)| access_token)
+    else {
+        return Ok(());
+    };
+
+    let claims = verify(&issuer.key, access_token).ok();
+    let Some(x5t) = claims
+        .as_ref()
+        .and_then(|claims| claims.private.get("cnf")?["x5t#S256"].as_str())
+    else {
+        return Ok(());
+    };
+
+    if thumbprint(req).as_deref() != Some(x5t) {
+        return Err(Error::oauth(
+            ErrorCode::InvalidToken,
+            "the access token is bound to a different certificate",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Respond to a request for a protected resource with an error
+pub fn challenge(err: &Error) -> HttpResponse {
+    let code: &'static str = err.code().into();
+
+    HttpResponse::Unauthorized()
+        .append_header((
+            header::WWW_AUTHENTICATE,
+            format!(
+                r#"Bearer error="{code}", error_description="{}""#,
+                err.description().replace('"', "'")
+            ),
+        ))
+        .finish()
+}
@@ -0,0 +1,270 @@
+//! Pushed Authorization Requests (RFC 9126)
+
+use super::{
+    helper::{basic_credentials, negotiated_scope, validated_redirect},
+    implicit::AuthorizationRequest,
+    issuer_url, request_object,
+};
+use crate::{
+    endpoints::{Error, ErrorCode, response::ResponseMode},
+    issuer::{Endpoint, IssuerState, PushedRequest},
+    server::state::ApplicationState,
+};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, dev::ConnectionInfo, http::header, middleware::from_fn,
+    post, web,
+};
+use chrono::Duration;
+use oxide_auth::{
+    endpoint::{Registrar, Scope},
+    frontends::simple::extensions::Pkce,
+};
+use serde_json::json;
+use std::{borrow::Cow, collections::HashSet};
+use url::Url;
+
+/// Lifetime of a pushed request, the client is expected to use it right away
+const PUSHED_REQUEST_LIFETIME: Duration = Duration::seconds(60);
+
+#[post("/{issuer}/par", wrap = "from_fn(super::mtls::authenticate)")]
+pub async fn pushed_authorization_request(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    path: web::Path<String>,
+    req: HttpRequest,
+    web::Form(parameters): web::Form<Vec<(String, String)>>,
+) -> Result<impl Responder, Error> {
+    let name = path.into_inner();
+
+    let issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+
+    let mut names = HashSet::new();
+    if let Some((name, _)) = parameters.iter().find(|(name, _)| !names.insert(name)) {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequest,
+            format!("duplicate parameter: {name}"),
+        ));
+    }
+    let value = |name: &str| {
+        parameters
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    };
+
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(basic_credentials);
+    let Some((client_id, secret)) = basic
+        .map(|(client_id, secret)| (client_id, Some(secret)))
+        .or_else(|| value("client_id").map(|client_id| (client_id, value("client_secret"))))
+    else {
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "missing client authentication",
+        ));
+    };
+
+    if value("client_id").is_some_and(|id| id != client_id) {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequest,
+            "client_id doesn't match the authenticated client",
+        ));
+    }
+    if value("request_uri").is_some() {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequest,
+            "request_uri must not be pushed",
+        ));
+    }
+
+    let inner = issuer.inner.read().await;
+    let registrar = &inner.endpoint.inner.registrar;
+    if registrar
+        .check(&client_id, secret.as_deref().map(str::as_bytes))
+        .is_err()
+    {
+        server
+            .metrics()
+            .client_authentication_failures
+            .with_label_values(&[&name, issuer.client_label(&client_id)])
+            .inc();
+        return Err(Error::oauth(
+            ErrorCode::InvalidClient,
+            "client authentication failed",
+        ));
+    }
+
+    // the client may have authenticated using basic authentication, but the request needs it
+    let object = value("request");
+    let mut parameters = parameters
+        .into_iter()
+        .filter(|(name, _)| name != "client_id" && name != "client_secret")
+        .collect::<Vec<_>>();
+    parameters.push(("client_id".into(), client_id.clone()));
+
+    // the parameters of a request object are validated as well, but it is stored as pushed
+    let issuer_url = issuer_url(&server, &conn, &name, [])?;
+    let resolved = match object {
+        Some(object) => request_object::resolve(&issuer, &issuer_url, parameters.clone(), &object)?,
+        None => parameters.clone(),
+    };
+    validate(&issuer, &inner.endpoint.inner, &issuer_url, &resolved)
+        .map_err(|(code, description)| Error::oauth(code, description))?;
+    drop(inner);
+
+    let request_uri = issuer
+        .pushed
+        .push(client_id, parameters, PUSHED_REQUEST_LIFETIME);
+
+    Ok(HttpResponse::Created().json(json!({
+        "request_uri": request_uri,
+        "expires_in": PUSHED_REQUEST_LIFETIME.num_seconds(),
+    })))
+}
+
+/// Check a pushed request the way the authorization endpoint does (RFC 9126, section 2.1)
+///
+/// Only the checks that don't depend on the user are made, the others are left to the
+/// authorization endpoint.
+fn validate(
+    issuer: &IssuerState,
+    endpoint: &Endpoint,
+    issuer_url: &Url,
+    parameters: &[(String, String)],
+) -> Result<(), (ErrorCode, &'static str)> {
+    let request = AuthorizationRequest {
+        resources: parameters
+            .iter()
+            .filter(|(name, _)| name == "resource")
+            .map(|(_, value)| value.clone())
+            .collect(),
+        ..AuthorizationRequest::from_query(|name| {
+            parameters
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        })
+    };
+    let client_id = request.client_id.as_deref().unwrap_or_default();
+
+    if validated_redirect(
+        &endpoint.registrar,
+        Some(client_id),
+        request.redirect_uri.as_deref(),
+    )
+    .is_none()
+    {
+        return Err((ErrorCode::InvalidRequest, "invalid redirect URI"));
+    }
+
+    let types = request.response_types();
+    if types.is_empty() {
+        return Err((ErrorCode::InvalidRequest, "missing response_type"));
+    }
+    if types
+        .iter()
+        .any(|t| !matches!(*t, "code" | "token" | "id_token"))
+    {
+        return Err((
+            ErrorCode::UnsupportedResponseType,
+            "unsupported response type",
+        ));
+    }
+    if request.is_implicit()
+        && !issuer
+            .client(client_id)
+            .is_some_and(|client| client.settings().allow_implicit)
+    {
+        return Err((
+            ErrorCode::UnauthorizedClient,
+            "client is not allowed to use the implicit or hybrid flow",
+        ));
+    }
+
+    match request.response_mode(&issuer.key, issuer_url) {
+        None => return Err((ErrorCode::InvalidRequest, "unknown response mode")),
+        Some(response_mode)
+            if request.is_implicit() && matches!(response_mode.delivery(), ResponseMode::Query) =>
+        {
+            return Err((
+                ErrorCode::InvalidRequest,
+                "the query response mode must not be used when returning tokens",
+            ));
+        }
+        Some(_) => {}
+    }
+
+    if types.contains("id_token") && request.nonce.is_none() {
+        return Err((ErrorCode::InvalidRequest, "missing nonce"));
+    }
+
+    let scope = request
+        .scope
+        .as_deref()
+        .map(str::parse::<Scope>)
+        .transpose()
+        .map_err(|_| (ErrorCode::InvalidScope, "invalid scope"))?;
+    let known = |requested: &str| {
+        endpoint
+            .scopes
+            .iter()
+            .any(|scope| scope.iter().any(|scope| scope == requested))
+    };
+    if scope.is_some_and(|scope| !scope.iter().all(known)) {
+        return Err((ErrorCode::InvalidScope, "unknown scope"));
+    }
+    if types.contains("id_token")
+        && !negotiated_scope(
+            &endpoint.registrar,
+            client_id,
+            request.redirect_uri.as_deref(),
+            request.scope.as_deref(),
+        )
+        .is_some_and(|scope| scope.iter().any(|scope| scope == "openid"))
+    {
+        return Err((
+            ErrorCode::InvalidScope,
+            "the openid scope is required for an ID token",
+        ));
+    }
+
+    if types.contains("code")
+        && Pkce::optional()
+            .challenge(
+                request.code_challenge_method.as_deref().map(Cow::Borrowed),
+                request.code_challenge.as_deref().map(Cow::Borrowed),
+            )
+            .is_err()
+    {
+        return Err((ErrorCode::InvalidRequest, "invalid code challenge"));
+    }
+
+    request.check_resources(issuer)
+}
+
+/// Resolve the `request_uri` of an authorization request
+///
+/// The request must have been pushed by the same client, and must not have expired.
+pub fn resolve(
+    issuer: &IssuerState,
+    request_uri: &str,
+    client_id: Option<&str>,
+) -> Result<PushedRequest, Error> {
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidRequestUri, description);
+
+    let request = issuer
+        .pushed
+        .get(request_uri)
+        .ok_or_else(|| invalid("unknown or expired request_uri"))?;
+
+    match client_id {
+        Some(client_id) if client_id == request.client_id => Ok(request),
+        Some(_) => Err(invalid("request_uri was pushed by a different client")),
+        None => Err(Error::oauth(ErrorCode::InvalidRequest, "missing client_id")),
+    }
+}
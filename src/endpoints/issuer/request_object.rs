@@ -0,0 +1,185 @@
+//! Signed request objects (JAR, RFC 9101)
+
+use crate::{
+    endpoints::{Error, ErrorCode},
+    issuer::IssuerState,
+    server::state::ApplicationState,
+};
+use biscuit::{ClaimsSet, Empty, SingleOrMultiple, jwa::SignatureAlgorithm, jws::Compact};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::time::Duration;
+use url::Url;
+
+/// Timeout for fetching a request object by reference
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of a request object fetched by reference
+const MAX_FETCH_SIZE: usize = 64 * 1024;
+
+/// Fetch a request object, passed by reference using the `request_uri` parameter
+///
+/// Only URIs registered for the client are fetched, and only if the client has keys to verify
+/// the request object with.
+pub async fn fetch(
+    server: &ApplicationState,
+    issuer: &IssuerState,
+    client_id: Option<&str>,
+    request_uri: &str,
+) -> Result<String, Error> {
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidRequestUri, description);
+
+    let client = client_id
+        .and_then(|client_id| issuer.client(client_id))
+        .ok_or_else(|| Error::oauth(ErrorCode::InvalidRequest, "unknown client"))?;
+    if client.settings().jwks.is_none() {
+        return Err(Error::oauth(
+            ErrorCode::InvalidRequestObject,
+            "the client has no keys to verify request objects",
+        ));
+    }
+
+    let url = Url::parse(request_uri).map_err(|_| invalid("invalid request_uri"))?;
+    if !matches!(url.scheme(), "https" | "http") {
+        return Err(invalid("unsupported request_uri scheme"));
+    }
+    if !client.settings().request_uris.contains(&url) {
+        return Err(invalid("request_uri is not registered for the client"));
+    }
+
+    let mut response = server
+        .http()
+        .get(url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| {
+            log::info!("Failed to fetch request object: {err}");
+            invalid("unable to fetch request_uri")
+        })?;
+
+    let too_large = || invalid("request object is too large");
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_FETCH_SIZE as u64)
+    {
+        return Err(too_large());
+    }
+    let mut object = vec![];
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|_| invalid("unable to fetch request_uri"))?
+    {
+        if object.len() + chunk.len() > MAX_FETCH_SIZE {
+            return Err(too_large());
+        }
+        object.extend_from_slice(&chunk);
+    }
+
+    let object = String::from_utf8(object).map_err(|_| invalid("unable to fetch request_uri"))?;
+    Ok(object.trim().to_string())
+}
+
+/// Verify a request object, merging its parameters into the ones of the request
+///
+/// The object must be signed with a key of the client, the parameters it carries take precedence
+/// over the ones outside of it.
+pub fn resolve(
+    issuer: &IssuerState,
+    issuer_url: &Url,
+    parameters: Vec<(String, String)>,
+    object: &str,
+) -> Result<Vec<(String, String)>, Error> {
+    let invalid = |description: &str| Error::oauth(ErrorCode::InvalidRequestObject, description);
+
+    let client_id = parameters
+        .iter()
+        .find(|(name, _)| name == "client_id")
+        .map(|(_, value)| value.as_str())
+        .ok_or_else(|| Error::oauth(ErrorCode::InvalidRequest, "missing client_id"))?;
+    let jwks = issuer
+        .client(client_id)
+        .and_then(|client| client.settings().jwks.as_ref())
+        .ok_or_else(|| invalid("the client has no keys to verify request objects"))?;
+
+    let token = Compact::<ClaimsSet<Map<String, Value>>, Empty>::new_encoded(object);
+    let header = token
+        .unverified_header()
+        .map_err(|_| invalid("malformed request object"))?;
+    if header.registered.algorithm == SignatureAlgorithm::None {
+        return Err(invalid("request object must be signed"));
+    }
+    let token = match header.registered.key_id {
+        Some(_) => token.decode_with_jwks(jwks, None),
+        None => token.decode_with_jwks_ignore_kid(jwks),
+    }
+    .map_err(|err| {
+        log::info!("Failed to verify request object: {err}");
+        invalid("invalid request object signature")
+    })?;
+    let claims = token
+        .payload()
+        .map_err(|_| invalid("malformed request object"))?;
+
+    if claims
+        .private
+        .get("client_id")
+        .is_some_and(|id| id.as_str() != Some(client_id))
+    {
+        return Err(invalid("client_id doesn't match the request object"));
+    }
+    if claims
+        .registered
+        .issuer
+        .as_deref()
+        .is_some_and(|iss| iss != client_id)
+    {
+        return Err(invalid("request object must be issued by the client"));
+    }
+    let audience = match &claims.registered.audience {
+        None => None,
+        Some(SingleOrMultiple::Single(aud)) => Some(vec![aud.as_str()]),
+        Some(SingleOrMultiple::Multiple(aud)) => Some(aud.iter().map(String::as_str).collect()),
+    };
+    if audience.is_some_and(|audience| !audience.contains(&issuer_url.as_str())) {
+        return Err(invalid(
+            "request object is intended for a different audience",
+        ));
+    }
+
+    let now = Utc::now();
+    if claims.registered.expiry.is_some_and(|exp| *exp <= now) {
+        return Err(invalid("request object has expired"));
+    }
+    if claims.registered.not_before.is_some_and(|nbf| *nbf > now) {
+        return Err(invalid("request object is not valid yet"));
+    }
+
+    // a request object can't refer to another one
+    let object = claims
+        .private
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "request" | "request_uri"))
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                // e.g. the claims parameter, which is a JSON object
+                value => value.to_string(),
+            };
+            (name.clone(), value)
+        })
+        .collect::<Vec<_>>();
+
+    let mut parameters = parameters
+        .into_iter()
+        .filter(|(name, _)| {
+            !matches!(name.as_str(), "request" | "request_uri")
+                && !object.iter().any(|(k, _)| k == name)
+        })
+        .collect::<Vec<_>>();
+    parameters.extend(object);
+
+    Ok(parameters)
+}
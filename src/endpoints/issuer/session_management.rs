@@ -0,0 +1,112 @@
+//! OpenID Connect Session Management
+//!
+//! The client embeds the `check_session` iframe and polls it with its `session_state`. The iframe
+//! recomputes the state from the browser state cookie and reports if it changed.
+
+use super::login::BROWSER_STATE_COOKIE;
+use crate::{endpoints::Error, server::state::ApplicationState};
+use actix_web::{HttpResponse, Responder, get, web};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// Compute the `session_state` of an authorization response
+///
+/// This is the hash of the client ID, the origin of the redirect URI, the browser state and a
+/// salt, followed by the salt.
+pub fn session_state(client_id: &str, redirect_uri: &Url, browser_state: &str) -> String {
+    let salt = uuid::Uuid::new_v4().simple().to_string();
+    session_state_salted(client_id, redirect_uri, browser_state, &salt)
+}
+
+fn session_state_salted(
+    client_id: &str,
+    redirect_uri: &Url,
+    browser_state: &str,
+    salt: &str,
+) -> String {
+    let origin = redirect_uri.origin().ascii_serialization();
+    let hash = Sha256::digest(format!("{client_id} {origin} {browser_state} {salt}"));
+    format!("{}.{salt}", BASE64_URL_SAFE_NO_PAD.encode(hash))
+}
+
+#[get("/{issuer}/check_session")]
+pub async fn check_session(
+    server: web::Data<ApplicationState>,
+    path: web::Path<String>,
+) -> Result<impl Responder, Error> {
+    let name = path.into_inner();
+
+    let _issuer = server
+        .issuer(&name)
+        .ok_or_else(|| Error::UnknownIssuer(name.clone()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .body(CHECK_SESSION_PAGE.replace("{cookie}", BROWSER_STATE_COOKIE)))
+}
+
+/// The OP iframe, answering `changed`, `unchanged` or `error` to the messages of the client
+const CHECK_SESSION_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Check session</title>
+    <script>
+      function browserState() {
+        const prefix = "{cookie}=";
+        const cookie = document.cookie.split("; ").find((c) => c.startsWith(prefix));
+        return cookie ? cookie.substring(prefix.length) : "";
+      }
+
+      async function sessionState(clientId, origin, salt) {
+        const data = new TextEncoder().encode(`${clientId} ${origin} ${browserState()} ${salt}`);
+        const hash = new Uint8Array(await crypto.subtle.digest("SHA-256", data));
+        const encoded = btoa(String.fromCharCode(...hash))
+          .replace(/\+/g, "-")
+          .replace(/\//g, "_")
+          .replace(/=+$/, "");
+        return `${encoded}.${salt}`;
+      }
+
+      window.addEventListener("message", async (event) => {
+        const [clientId, state] = typeof event.data === "string" ? event.data.split(" ") : [];
+        const salt = state ? state.substring(state.lastIndexOf(".") + 1) : "";
+        if (!clientId || !salt || !state.includes(".")) {
+          event.source.postMessage("error", event.origin);
+          return;
+        }
+        const current = await sessionState(clientId, event.origin, salt);
+        event.source.postMessage(current === state ? "unchanged" : "changed", event.origin);
+      });
+    </script>
+  </head>
+  <body></body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn session_state_hash() -> anyhow::Result<()> {
+        let redirect_uri = "http://localhost:3000/cb?foo=bar".parse()?;
+        let state = session_state_salted("frontend", &redirect_uri, "browser", "salt");
+
+        let expected = Sha256::digest("frontend http://localhost:3000 browser salt");
+        assert_eq!(
+            state,
+            format!("{}.salt", BASE64_URL_SAFE_NO_PAD.encode(expected))
+        );
+
+        // the salt is random, the origin ignores the path
+        let other = session_state("frontend", &"http://localhost:3000/".parse()?, "browser");
+        let (_, salt) = other.rsplit_once('.').unwrap_or_default();
+        assert_eq!(
+            other,
+            session_state_salted("frontend", &redirect_uri, "browser", salt)
+        );
+
+        Ok(())
+    }
+}
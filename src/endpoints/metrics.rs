@@ -0,0 +1,78 @@
+use crate::{endpoints::Error, server::state::ApplicationState};
+use actix_web::{HttpResponse, Responder, get, web};
+
+/// Metrics, in the Prometheus text format
+#[get("/metrics")]
+pub async fn metrics(app: web::Data<ApplicationState>) -> Result<impl Responder, Error> {
+    let metrics = app
+        .metrics()
+        .encode()
+        .map_err(|err| Error::Generic(err.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        issuer::{Client, Issuer},
+        server::app::Application,
+    };
+    use actix_web::{App, http::StatusCode, test};
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn metrics() -> anyhow::Result<()> {
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            });
+        let issuers = HashMap::from([("test".to_string(), issuer)]);
+        let app = Application::new("http://localhost".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        for (client_id, secret) in [
+            ("client", "secret"),
+            ("client", "wrong"),
+            ("made-up", "secret"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/test/token")
+                .set_form([
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", secret),
+                ])
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        let req = test::TestRequest::get()
+            .uri("/test/auth?client_id=made-up&response_type=code")
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec())?;
+
+        for line in [
+            r#"garage_door_tokens_issued_total{client="client",grant_type="client_credentials",issuer="test"} 1"#,
+            r#"garage_door_client_authentication_failures_total{client="client",issuer="test"} 1"#,
+            r#"garage_door_client_authentication_failures_total{client="unknown",issuer="test"} 1"#,
+            r#"garage_door_token_errors_total{error="invalid_client",issuer="test"} 2"#,
+            r#"garage_door_authorization_requests_total{client="unknown",issuer="test"} 1"#,
+        ] {
+            assert!(body.lines().any(|l| l == line), "missing: {line}\n{body}");
+        }
+        // clients which aren't registered don't get a series of their own
+        assert!(!body.contains("made-up"));
+
+        Ok(())
+    }
+}
@@ -1,13 +1,78 @@
 use crate::server::state::ApplicationState;
 use actix_web::body::BoxBody;
+use actix_web::http::{StatusCode, header};
 use actix_web::{HttpResponse, Responder, ResponseError, get, web};
+use oxide_auth::endpoint::OAuthError;
 use oxide_auth_actix::WebError;
+use response::ResponseMode;
 use serde::Serialize;
+use url::Url;
 
-#[derive(Debug, thiserror::Error, strum::IntoStaticStr)]
+/// OAuth error codes, as defined by RFC 6749 and OpenID Connect
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, strum::IntoStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    InvalidScope,
+    AccessDenied,
+    ServerError,
+    TemporarilyUnavailable,
+    // OpenID Connect
+    LoginRequired,
+    InteractionRequired,
+    ConsentRequired,
+    // RFC 9101
+    InvalidRequestUri,
+    InvalidRequestObject,
+    // RFC 6750
+    InvalidToken,
+    // RFC 8707
+    InvalidTarget,
+    // RFC 9449
+    InvalidDpopProof,
+    UseDpopNonce,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidClient | Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::AccessDenied => StatusCode::FORBIDDEN,
+            Self::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TemporarilyUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InvalidRequest
+            | Self::InvalidGrant
+            | Self::UnauthorizedClient
+            | Self::UnsupportedGrantType
+            | Self::UnsupportedResponseType
+            | Self::InvalidScope
+            | Self::LoginRequired
+            | Self::InteractionRequired
+            | Self::ConsentRequired
+            | Self::InvalidRequestUri
+            | Self::InvalidRequestObject
+            | Self::InvalidTarget
+            | Self::InvalidDpopProof
+            | Self::UseDpopNonce => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("unknown issuer: {0}")]
     UnknownIssuer(String),
+    #[error("unknown resource: {0}")]
+    UnknownResource(String),
+    /// The endpoint requires a login session of the user agent
+    #[error("no login session")]
+    Unauthenticated,
     #[error("url error")]
     Url,
     #[error("url parse error: {0}")]
@@ -18,28 +83,143 @@ pub enum Error {
     Oxide(#[from] WebError),
     #[error("generic error: {0}")]
     Generic(String),
+    #[error("{description}")]
+    OAuth {
+        code: ErrorCode,
+        description: String,
+    },
+    /// The DPoP proof must carry a nonce, the one to use is provided to the client
+    #[error("a DPoP nonce is required")]
+    DpopNonce(String),
+    /// An error which must be delivered to the client by redirecting the user agent
+    #[error("{}", .0.error)]
+    Redirect(Box<ErrorRedirect>),
+}
+
+/// Where and how an error is delivered to the client
+#[derive(Debug)]
+pub struct ErrorRedirect {
+    pub error: Error,
+    pub redirect_uri: Url,
+    pub state: Option<String>,
+    pub response_mode: ResponseMode,
 }
 
 #[derive(Serialize)]
 struct ErrorInformation {
-    error: &'static str,
-    message: String,
+    error: ErrorCode,
+    error_description: String,
 }
 
 impl Error {
+    pub fn oauth(code: ErrorCode, description: impl Into<String>) -> Self {
+        Self::OAuth {
+            code,
+            description: description.into(),
+        }
+    }
+
+    /// Deliver the error to the client by redirecting, only use with a validated redirect URI
+    pub fn redirect(
+        self,
+        redirect_uri: Url,
+        state: Option<String>,
+        response_mode: ResponseMode,
+    ) -> Self {
+        Self::Redirect(Box::new(ErrorRedirect {
+            error: self,
+            redirect_uri,
+            state,
+            response_mode,
+        }))
+    }
+
+    /// The OAuth error code
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnknownIssuer(_) | Self::UnknownResource(_) | Self::UrlParse(_) => {
+                ErrorCode::InvalidRequest
+            }
+            Self::Unauthenticated => ErrorCode::LoginRequired,
+            Self::Url | Self::Serde(_) | Self::Generic(_) => ErrorCode::ServerError,
+            Self::Oxide(err) => match err {
+                WebError::Endpoint(OAuthError::BadRequest | OAuthError::DenySilently)
+                | WebError::Encoding
+                | WebError::Form
+                | WebError::Query
+                | WebError::Body => ErrorCode::InvalidRequest,
+                WebError::Authorization => ErrorCode::InvalidClient,
+                _ => ErrorCode::ServerError,
+            },
+            Self::OAuth { code, .. } => *code,
+            Self::DpopNonce(_) => ErrorCode::UseDpopNonce,
+            Self::Redirect(redirect) => redirect.error.code(),
+        }
+    }
+
+    /// A human-readable description, suitable for `error_description`
+    pub fn description(&self) -> String {
+        match self {
+            Self::Oxide(WebError::Endpoint(OAuthError::DenySilently)) => {
+                "unknown client or invalid redirect URI".into()
+            }
+            Self::Oxide(WebError::Endpoint(OAuthError::BadRequest)) => "malformed request".into(),
+            Self::Redirect(redirect) => redirect.error.description(),
+            _ => self.to_string(),
+        }
+    }
+
     fn to_body(&self) -> ErrorInformation {
         ErrorInformation {
-            error: self.into(),
-            message: self.to_string(),
+            error: self.code(),
+            error_description: self.description(),
         }
     }
 }
 
 impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownIssuer(_) | Self::UnknownResource(_) => StatusCode::NOT_FOUND,
+            Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Self::Redirect(redirect) => match redirect.response_mode.delivery() {
+                ResponseMode::FormPost => StatusCode::OK,
+                _ => StatusCode::FOUND,
+            },
+            _ => self.code().status(),
+        }
+    }
+
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        match &self {
-            Self::UnknownIssuer(_) => HttpResponse::NotFound().json(self.to_body()),
-            _ => HttpResponse::InternalServerError().json(self.to_body()),
+        match self {
+            Self::Redirect(redirect) => {
+                let ErrorRedirect {
+                    error,
+                    redirect_uri,
+                    state,
+                    response_mode,
+                } = redirect.as_ref();
+                let description = error.description();
+                let mut params = vec![
+                    ("error", error.code().into()),
+                    ("error_description", description.as_str()),
+                ];
+                if let Some(state) = state {
+                    params.push(("state", state));
+                }
+                response_mode.deliver(redirect_uri, params)
+            }
+            Self::OAuth {
+                code: ErrorCode::InvalidClient,
+                ..
+            }
+            | Self::Oxide(WebError::Authorization) => HttpResponse::Unauthorized()
+                .append_header((header::WWW_AUTHENTICATE, "Basic"))
+                .json(self.to_body()),
+            Self::DpopNonce(nonce) => HttpResponse::build(self.status_code())
+                .append_header(("DPoP-Nonce", nonce.as_str()))
+                .json(self.to_body()),
+            _ => HttpResponse::build(self.status_code()).json(self.to_body()),
         }
     }
 }
@@ -64,4 +244,60 @@ Issuers:
     )
 }
 
+pub mod health;
 pub mod issuer;
+pub mod metrics;
+pub mod response;
+pub mod well_known;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redirect_error() -> anyhow::Result<()> {
+        let error = Error::oauth(ErrorCode::InvalidScope, "unknown scope").redirect(
+            "http://localhost/cb?foo=bar".parse()?,
+            Some("xyz".into()),
+            ResponseMode::Query,
+        );
+
+        let resp = error.error_response();
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "http://localhost/cb?foo=bar&error=invalid_scope&error_description=unknown+scope&state=xyz"
+        );
+
+        let error = Error::oauth(ErrorCode::AccessDenied, "denied").redirect(
+            "http://localhost/cb".parse()?,
+            None,
+            ResponseMode::Fragment,
+        );
+        let resp = error.error_response();
+        assert_eq!(
+            resp.headers().get(header::LOCATION).unwrap(),
+            "http://localhost/cb#error=access_denied&error_description=denied"
+        );
+
+        let resp = Error::UnknownIssuer("foo".into()).error_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = Error::UrlParse(url::ParseError::EmptyHost).error_response();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        for error in [
+            Error::oauth(ErrorCode::InvalidClient, "unknown client"),
+            Error::Oxide(WebError::Authorization),
+        ] {
+            let resp = error.error_response();
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                resp.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+                "Basic"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use crate::issuer::JwtResponseGenerator;
+use actix_web::{
+    HttpResponse,
+    http::header::{self, CacheControl, CacheDirective},
+};
+use url::{Url, form_urlencoded};
+
+/// How the parameters of an authorization response are delivered to the client
+#[derive(Clone, Debug, Default)]
+pub enum ResponseMode {
+    #[default]
+    Query,
+    Fragment,
+    /// OAuth 2.0 Form Post Response Mode
+    FormPost,
+    /// JWT Secured Authorization Response Mode, wrapping the parameters into a signed JWT
+    Jwt(Box<JwtResponseMode>),
+}
+
+/// The JWT response mode, based on a plain response mode
+#[derive(Clone, Debug)]
+pub struct JwtResponseMode {
+    /// How the JWT is delivered
+    pub delivery: ResponseMode,
+    pub generator: JwtResponseGenerator,
+}
+
+impl ResponseMode {
+    /// Parse the value of the `response_mode` parameter
+    ///
+    /// This only covers the plain response modes, see [`Self::jwt`] for the JWT ones.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "query" => Some(Self::Query),
+            "fragment" => Some(Self::Fragment),
+            "form_post" => Some(Self::FormPost),
+            _ => None,
+        }
+    }
+
+    /// Deliver the parameters wrapped into a JWT, signed by the generator
+    pub fn jwt(self, generator: JwtResponseGenerator) -> Self {
+        Self::Jwt(Box::new(JwtResponseMode {
+            delivery: self.delivery().clone(),
+            generator,
+        }))
+    }
+
+    /// How the parameters, or the JWT carrying them, get to the client
+    pub fn delivery(&self) -> &Self {
+        match self {
+            Self::Jwt(jwt) => jwt.delivery.delivery(),
+            _ => self,
+        }
+    }
+
+    /// Deliver the parameters to the redirect URI of the client
+    pub fn deliver<'a>(
+        &self,
+        redirect_uri: &Url,
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> HttpResponse {
+        let mut location = redirect_uri.clone();
+
+        match self {
+            Self::Query => {
+                location.query_pairs_mut().extend_pairs(params);
+            }
+            Self::Fragment => {
+                let fragment = form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(params)
+                    .finish();
+                location.set_fragment(Some(&fragment));
+            }
+            Self::FormPost => return form_post(redirect_uri, params),
+            Self::Jwt(jwt) => {
+                return match jwt.generator.create(params) {
+                    Ok(response) => jwt
+                        .delivery
+                        .deliver(redirect_uri, [("response", response.as_str())]),
+                    Err(err) => {
+                        log::warn!("Unable to create response JWT: {err}");
+                        HttpResponse::InternalServerError().finish()
+                    }
+                };
+            }
+        }
+
+        HttpResponse::Found()
+            .append_header((header::LOCATION, location.to_string()))
+            .finish()
+    }
+}
+
+/// Render a page which posts the parameters to the client
+fn form_post<'a>(
+    redirect_uri: &Url,
+    params: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> HttpResponse {
+    let inputs = params
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                r#"<input type="hidden" name="{}" value="{}"/>"#,
+                escape(name),
+                escape(value)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n      ");
+
+    let action = escape(redirect_uri.as_str());
+
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .content_type(mime::TEXT_HTML_UTF_8)
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html>
+  <head><title>Submit this form</title></head>
+  <body onload="javascript:document.forms[0].submit()">
+    <form method="post" action="{action}">
+      {inputs}
+      <noscript><button type="submit">Continue</button></noscript>
+    </form>
+  </body>
+</html>
+"#
+        ))
+}
+
+/// Escape a value for use in HTML text and attributes
+pub fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#x27;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -0,0 +1,152 @@
+use crate::{
+    endpoints::{Error, issuer::issuer_url},
+    server::state::ApplicationState,
+};
+use actix_web::{HttpResponse, Responder, dev::ConnectionInfo, get, web};
+use serde::{Deserialize, Serialize};
+
+/// The WebFinger relation type for finding the OpenID Connect issuer
+const ISSUER_REL: &str = "http://openid.net/specs/connect/1.0/issuer";
+
+/// Authorization server metadata, using the path-inserted form of RFC 8414
+#[get("/.well-known/oauth-authorization-server/{issuer}")]
+pub async fn authorization_server(
+    server: web::Data<ApplicationState>,
+    path: web::Path<String>,
+    conn: ConnectionInfo,
+) -> Result<impl Responder, Error> {
+    metadata(&server, &conn, path.into_inner()).await
+}
+
+/// OpenID Connect discovery, using the path-inserted form of RFC 8414
+#[get("/.well-known/openid-configuration/{issuer}")]
+pub async fn openid_configuration(
+    server: web::Data<ApplicationState>,
+    path: web::Path<String>,
+    conn: ConnectionInfo,
+) -> Result<impl Responder, Error> {
+    metadata(&server, &conn, path.into_inner()).await
+}
+
+async fn metadata(
+    server: &ApplicationState,
+    conn: &ConnectionInfo,
+    name: String,
+) -> Result<HttpResponse, Error> {
+    let base = issuer_url(server, conn, &name, [])?;
+
+    let issuer = server.issuer(&name).ok_or(Error::UnknownIssuer(name))?;
+
+    Ok(HttpResponse::Ok().json(issuer.discovery(base).await?))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebFingerQuery {
+    pub resource: String,
+    pub rel: Option<String>,
+}
+
+/// JSON Resource Descriptor, RFC 7033
+#[derive(Clone, Debug, Serialize)]
+struct ResourceDescriptor {
+    subject: String,
+    links: Vec<Link>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Link {
+    rel: &'static str,
+    href: String,
+}
+
+/// WebFinger, resolving `acct:` identifiers of users to the issuers they belong to
+#[get("/.well-known/webfinger")]
+pub async fn webfinger(
+    server: web::Data<ApplicationState>,
+    conn: ConnectionInfo,
+    web::Query(WebFingerQuery { resource, rel }): web::Query<WebFingerQuery>,
+) -> Result<impl Responder, Error> {
+    let (user, host) = resource
+        .strip_prefix("acct:")
+        .and_then(|account| account.rsplit_once('@'))
+        .ok_or_else(|| Error::UnknownResource(resource.clone()))?;
+
+    // the port is not part of an account identifier
+    let own_host = conn.host();
+    let own_host = own_host
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .map_or(own_host, |(host, _)| host);
+    if !host.eq_ignore_ascii_case(own_host) {
+        return Err(Error::UnknownResource(resource));
+    }
+
+    let mut issuers = server.issuers();
+    issuers.sort_unstable();
+
+    let mut links = vec![];
+    for name in issuers {
+        let Some(issuer) = server.issuer(&name) else {
+            continue;
+        };
+        if issuer.user(user).is_some() {
+            links.push(Link {
+                rel: ISSUER_REL,
+                href: issuer_url(&server, &conn, &name, [])?.to_string(),
+            });
+        }
+    }
+
+    if links.is_empty() {
+        return Err(Error::UnknownResource(resource));
+    }
+
+    if let Some(rel) = rel {
+        links.retain(|link| link.rel == rel);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/jrd+json")
+        .json(ResourceDescriptor {
+            subject: resource,
+            links,
+        }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{issuer::Issuer, server::app::Application};
+    use actix_web::{App, http::StatusCode, test};
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn webfinger() -> anyhow::Result<()> {
+        let issuers = HashMap::from([("test".to_string(), Issuer::new("key", ["openid"])?)]);
+        let app = Application::new("http://localhost".parse()?, None, issuers)?;
+        let app = test::init_service(App::new().configure(|svc| app.configure(svc))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/webfinger?resource=acct:Marvin@localhost&rel=http://openid.net/specs/connect/1.0/issuer")
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp,
+            json!({
+                "subject": "acct:Marvin@localhost",
+                "links": [{
+                    "rel": "http://openid.net/specs/connect/1.0/issuer",
+                    "href": "http://localhost:8080/test",
+                }]
+            })
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/webfinger?resource=acct:Arthur@localhost")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
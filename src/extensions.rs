@@ -1,10 +1,12 @@
 use actix_web::dev::ConnectionInfo;
+use chrono::{DateTime, Utc};
 use oxide_auth::{
-    code_grant::accesstoken::Request,
+    code_grant::{accesstoken::Request, authorization::Request as AuthorizationRequest},
     frontends::simple::extensions::{
-        AccessTokenAddon, AddonResult, ClientCredentialsAddon, ClientCredentialsRequest,
+        AccessTokenAddon, AddonResult, AuthorizationAddon, ClientCredentialsAddon,
+        ClientCredentialsRequest,
     },
-    primitives::grant::{GrantExtension, Value},
+    primitives::grant::{Grant, GrantExtension, Value},
 };
 use std::borrow::Cow;
 
@@ -31,7 +33,7 @@ impl ConnectionInformation {
         )
     }
 
-    pub fn decode(s: &str) -> Option<ConnectionInformationData> {
+    pub fn decode(s: &str) -> Option<ConnectionInformationData<'_>> {
         serde_json::from_str(s).ok()
     }
 }
@@ -55,3 +57,236 @@ impl ClientCredentialsAddon for ConnectionInformation {
         AddonResult::Data(self.encode())
     }
 }
+
+/// Information about the authentication of the user, carried from the authorization request to
+/// the tokens
+///
+/// For authorization requests this holds the data to add, for access token requests it passes on
+/// the data of the authorization code.
+#[derive(Clone, Debug, Default)]
+pub struct Authentication(pub Option<AuthenticationData>);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuthenticationData {
+    /// When the user was authenticated, as seconds since the epoch
+    pub auth_time: i64,
+    pub nonce: Option<String>,
+    /// The public ID of the login session
+    #[serde(default)]
+    pub sid: Option<String>,
+}
+
+impl AuthenticationData {
+    pub fn auth_time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.auth_time, 0)
+    }
+}
+
+impl Authentication {
+    pub fn id() -> &'static str {
+        "garage_door::authentication"
+    }
+
+    /// Get the authentication data stored with a grant
+    pub fn from_grant(grant: &Grant) -> Option<AuthenticationData> {
+        grant
+            .extensions
+            .private()
+            .filter_map(|(k, v)| if k == Self::id() { v } else { None })
+            .find_map(|v| serde_json::from_str(v).ok())
+    }
+}
+
+impl GrantExtension for Authentication {
+    fn identifier(&self) -> &'static str {
+        Self::id()
+    }
+}
+
+impl AuthorizationAddon for Authentication {
+    fn execute(&self, _request: &dyn AuthorizationRequest) -> AddonResult {
+        match &self.0 {
+            Some(data) => AddonResult::Data(Value::Private(serde_json::to_string(data).ok())),
+            None => AddonResult::Ok,
+        }
+    }
+}
+
+impl AccessTokenAddon for Authentication {
+    fn execute(&self, _request: &dyn Request, code_data: Option<Value>) -> AddonResult {
+        match code_data {
+            Some(data) => AddonResult::Data(data),
+            None => AddonResult::Ok,
+        }
+    }
+}
+
+/// The thumbprint of the DPoP key an access token is bound to
+#[derive(Clone, Debug, Default)]
+pub struct DpopBinding(pub Option<String>);
+
+impl DpopBinding {
+    pub fn id() -> &'static str {
+        "garage_door::dpop"
+    }
+
+    /// Get the thumbprint of the key a grant is bound to
+    pub fn from_grant(grant: &Grant) -> Option<String> {
+        grant
+            .extensions
+            .private()
+            .filter_map(|(k, v)| if k == Self::id() { v } else { None })
+            .map(ToString::to_string)
+            .next()
+    }
+
+    fn data(&self) -> AddonResult {
+        match &self.0 {
+            Some(jkt) => AddonResult::Data(Value::Private(Some(jkt.clone()))),
+            None => AddonResult::Ok,
+        }
+    }
+}
+
+impl GrantExtension for DpopBinding {
+    fn identifier(&self) -> &'static str {
+        Self::id()
+    }
+}
+
+impl AccessTokenAddon for DpopBinding {
+    fn execute(&self, _request: &dyn Request, _code_data: Option<Value>) -> AddonResult {
+        self.data()
+    }
+}
+
+impl ClientCredentialsAddon for DpopBinding {
+    fn execute(&self, _request: &dyn ClientCredentialsRequest) -> AddonResult {
+        self.data()
+    }
+}
+
+/// The thumbprint of the TLS client certificate an access token is bound to
+#[derive(Clone, Debug, Default)]
+pub struct CertificateBinding(pub Option<String>);
+
+impl CertificateBinding {
+    pub fn id() -> &'static str {
+        "garage_door::mtls"
+    }
+
+    /// Get the thumbprint of the certificate a grant is bound to
+    pub fn from_grant(grant: &Grant) -> Option<String> {
+        grant
+            .extensions
+            .private()
+            .filter_map(|(k, v)| if k == Self::id() { v } else { None })
+            .map(ToString::to_string)
+            .next()
+    }
+
+    fn data(&self) -> AddonResult {
+        match &self.0 {
+            Some(x5t) => AddonResult::Data(Value::Private(Some(x5t.clone()))),
+            None => AddonResult::Ok,
+        }
+    }
+}
+
+impl GrantExtension for CertificateBinding {
+    fn identifier(&self) -> &'static str {
+        Self::id()
+    }
+}
+
+impl AccessTokenAddon for CertificateBinding {
+    fn execute(&self, _request: &dyn Request, _code_data: Option<Value>) -> AddonResult {
+        self.data()
+    }
+}
+
+impl ClientCredentialsAddon for CertificateBinding {
+    fn execute(&self, _request: &dyn ClientCredentialsRequest) -> AddonResult {
+        self.data()
+    }
+}
+
+/// The resources an access token is requested for, using resource indicators (RFC 8707)
+///
+/// For authorization requests this holds the requested resources, stored with the authorization
+/// code. Token requests may narrow them down to a single resource using the `resource` parameter.
+#[derive(Clone, Debug, Default)]
+pub struct Resources(pub Vec<String>);
+
+impl Resources {
+    pub fn id() -> &'static str {
+        "garage_door::resources"
+    }
+
+    /// Get the resources a grant was requested for
+    pub fn from_grant(grant: &Grant) -> Vec<String> {
+        grant
+            .extensions
+            .private()
+            .filter_map(|(k, v)| if k == Self::id() { v } else { None })
+            .find_map(|v| serde_json::from_str(v).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn encode(&self) -> Value {
+        Value::Private(serde_json::to_string(&self.0).ok())
+    }
+
+    fn data(resources: Vec<String>) -> AddonResult {
+        if resources.is_empty() {
+            AddonResult::Ok
+        } else {
+            AddonResult::Data(Self(resources).encode())
+        }
+    }
+}
+
+impl GrantExtension for Resources {
+    fn identifier(&self) -> &'static str {
+        Self::id()
+    }
+}
+
+impl AuthorizationAddon for Resources {
+    fn execute(&self, _request: &dyn AuthorizationRequest) -> AddonResult {
+        Self::data(self.0.clone())
+    }
+}
+
+impl AccessTokenAddon for Resources {
+    fn execute(&self, request: &dyn Request, code_data: Option<Value>) -> AddonResult {
+        let authorized = code_data
+            .as_ref()
+            .and_then(|data| match data {
+                Value::Private(data) => data.as_deref(),
+                Value::Public(_) => None,
+            })
+            .and_then(|data| serde_json::from_str::<Vec<String>>(data).ok())
+            .unwrap_or_default();
+
+        match request.extension("resource") {
+            None => Self::data(authorized),
+            // the resource must be one of the ones the user authorized
+            Some(resource)
+                if !authorized.is_empty() && !authorized.iter().any(|r| *r == resource) =>
+            {
+                AddonResult::Err
+            }
+            Some(resource) => Self::data(vec![resource.into_owned()]),
+        }
+    }
+}
+
+impl ClientCredentialsAddon for Resources {
+    fn execute(&self, request: &dyn ClientCredentialsRequest) -> AddonResult {
+        match request.extension("resource") {
+            Some(resource) => Self::data(vec![resource.into_owned()]),
+            None => AddonResult::Ok,
+        }
+    }
+}
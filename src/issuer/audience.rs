@@ -0,0 +1,128 @@
+use super::{Client, DEFAULT_AUDIENCE};
+use oxide_auth::primitives::scope::Scope;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use url::Url;
+
+/// The audiences and resources a client is configured with
+#[derive(Clone, Debug, Default)]
+struct ClientAudiences {
+    audiences: Vec<String>,
+    resources: Vec<String>,
+}
+
+/// Decides the audiences of the access tokens of an issuer
+#[derive(Clone, Debug, Default)]
+pub struct AudiencePolicy {
+    scopes: Arc<BTreeMap<String, Vec<String>>>,
+    clients: Arc<HashMap<String, ClientAudiences>>,
+}
+
+impl AudiencePolicy {
+    pub fn new(scopes: BTreeMap<String, Vec<String>>, clients: &[Client]) -> Self {
+        let clients = clients
+            .iter()
+            .map(|client| {
+                let settings = client.settings();
+                (
+                    client.id().to_string(),
+                    ClientAudiences {
+                        audiences: settings.audiences.clone(),
+                        resources: settings.resources.clone(),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            scopes: Arc::new(scopes),
+            clients: Arc::new(clients),
+        }
+    }
+
+    /// The audiences of an access token which didn't request any resource
+    ///
+    /// These are the default audiences of the client, and the audiences of the granted scopes.
+    /// Falls back to [`DEFAULT_AUDIENCE`] if none are configured.
+    pub fn audiences(&self, client_id: &str, scope: &Scope) -> Vec<String> {
+        let client = self
+            .clients
+            .get(client_id)
+            .map(|client| client.audiences.as_slice())
+            .unwrap_or_default();
+        let scopes = scope
+            .iter()
+            .filter_map(|scope| self.scopes.get(scope))
+            .flatten();
+
+        let mut audiences = Vec::<String>::new();
+        for audience in client.iter().chain(scopes) {
+            if !audiences.contains(audience) {
+                audiences.push(audience.clone());
+            }
+        }
+
+        if audiences.is_empty() {
+            audiences.push(DEFAULT_AUDIENCE.to_string());
+        }
+        audiences
+    }
+
+    /// Check if the client may request access tokens for a resource (RFC 8707)
+    pub fn allows_resource(&self, client_id: &str, resource: &str) -> bool {
+        self.clients
+            .get(client_id)
+            .is_some_and(|client| client.resources.iter().any(|r| r == resource))
+    }
+}
+
+/// Check if a value is a valid resource indicator: an absolute URI, without a fragment
+pub fn is_valid_resource(resource: &str) -> bool {
+    Url::parse(resource).is_ok_and(|url| url.fragment().is_none())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::issuer::ClientSettings;
+
+    #[test]
+    fn audiences() {
+        let client = Client::Confidential {
+            id: "client".into(),
+            secret: "secret".to_string().into(),
+            default_scope: "openid".into(),
+            settings: ClientSettings {
+                audiences: vec!["api".into()],
+                resources: vec!["https://api.example.com".into()],
+                ..Default::default()
+            },
+        };
+        let policy = AudiencePolicy::new(
+            BTreeMap::from([
+                ("read".to_string(), vec!["api".to_string()]),
+                ("admin".to_string(), vec!["admin-api".to_string()]),
+            ]),
+            &[client],
+        );
+
+        assert_eq!(
+            policy.audiences("client", &"openid read admin".parse().unwrap()),
+            vec!["api", "admin-api"]
+        );
+        assert_eq!(
+            policy.audiences("other", &"openid".parse().unwrap()),
+            vec![DEFAULT_AUDIENCE]
+        );
+
+        assert!(policy.allows_resource("client", "https://api.example.com"));
+        assert!(!policy.allows_resource("client", "https://api.example.com/"));
+        assert!(!policy.allows_resource("other", "https://api.example.com"));
+
+        assert!(is_valid_resource("https://api.example.com"));
+        assert!(!is_valid_resource("api"));
+        assert!(!is_valid_resource("https://api.example.com#fragment"));
+    }
+}
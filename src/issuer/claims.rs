@@ -0,0 +1,300 @@
+use super::{Client, Group, User};
+use oxide_auth::primitives::scope::Scope;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, sync::Arc};
+
+/// Claims which are set by the issuer itself, and can't be mapped
+pub const RESERVED_CLAIMS: &[&str] = &[
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "nbf",
+    "iat",
+    "jti",
+    "azp",
+    "auth_time",
+    "scope",
+    "cnf",
+    "nonce",
+    "at_hash",
+    "c_hash",
+    "sid",
+];
+
+/// Check if a claim is set by the issuer, or nested in one which is
+pub fn is_reserved_claim(name: &str) -> bool {
+    let root = name.split_once('.').map_or(name, |(root, _)| root);
+    RESERVED_CLAIMS.contains(&root)
+}
+
+/// A claim added to the tokens and userinfo of a user
+#[derive(
+    Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimMapping {
+    /// The name of the claim, nested claims are separated by dots, e.g. `realm_access.roles`
+    pub name: String,
+    pub value: ClaimValue,
+    /// Where the claim is added, defaults to all of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ClaimTarget>,
+    /// Only add the claim when this scope is granted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// Where the value of a claim comes from
+#[derive(
+    Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ClaimValue {
+    /// A fixed value, the same for all users
+    Static(Value),
+    /// An attribute of the user, the claim is left out if the user doesn't have it
+    Attribute(String),
+    /// The groups of the user, including the ones inherited through nested groups
+    Groups,
+    /// The roles of the user for the client the claim is issued to
+    Roles,
+    /// The roles of the user for another client
+    ClientRoles(String),
+}
+
+/// What a claim is added to
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ClaimTarget {
+    AccessToken,
+    IdToken,
+    Userinfo,
+}
+
+impl ClaimMapping {
+    pub fn applies_to(&self, target: ClaimTarget) -> bool {
+        self.targets.is_empty() || self.targets.contains(&target)
+    }
+
+    /// Check if the claim may be added for the granted scope, which is unknown for `None`
+    pub fn allowed_by(&self, scope: Option<&Scope>) -> bool {
+        match &self.scope {
+            None => true,
+            Some(required) => scope.is_some_and(|scope| scope.iter().any(|s| s == required)),
+        }
+    }
+
+    /// The value of the claim for a user, if there is any
+    fn resolve(
+        &self,
+        user: Option<&User>,
+        groups: &[Group],
+        client_id: Option<&str>,
+    ) -> Option<Value> {
+        match &self.value {
+            ClaimValue::Static(value) => Some(value.clone()),
+            ClaimValue::Attribute(name) => user?.attributes.get(name).cloned(),
+            ClaimValue::Groups => Some(user?.effective_groups(groups).into()),
+            ClaimValue::Roles => Some(user?.client_roles(client_id?, groups).into()),
+            ClaimValue::ClientRoles(client_id) => {
+                Some(user?.client_roles(client_id, groups).into())
+            }
+        }
+    }
+}
+
+/// The claim mappings of an issuer, with the ones of its clients
+#[derive(Clone, Debug, Default)]
+pub struct ClaimsPolicy {
+    issuer: Arc<Vec<ClaimMapping>>,
+    clients: Arc<HashMap<String, Vec<ClaimMapping>>>,
+    users: Arc<Vec<User>>,
+    groups: Arc<Vec<Group>>,
+}
+
+impl ClaimsPolicy {
+    pub fn new(
+        issuer: Vec<ClaimMapping>,
+        clients: &[Client],
+        users: Arc<Vec<User>>,
+        groups: Vec<Group>,
+    ) -> Self {
+        let clients = clients
+            .iter()
+            .map(|client| (client.id().to_string(), client.settings().claims.clone()))
+            .collect();
+
+        Self {
+            issuer: Arc::new(issuer),
+            clients: Arc::new(clients),
+            users,
+            groups: Arc::new(groups),
+        }
+    }
+
+    /// The mapped claims of a subject, for a client and the scope granted to it
+    ///
+    /// The mappings of the client come after the ones of the issuer, overriding claims of the
+    /// same name.
+    pub fn claims(
+        &self,
+        target: ClaimTarget,
+        subject: &str,
+        client_id: Option<&str>,
+        scope: Option<&Scope>,
+    ) -> Map<String, Value> {
+        let user = self.users.iter().find(|user| user.id == subject);
+        let client = client_id
+            .and_then(|client_id| self.clients.get(client_id))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut claims = Map::new();
+        for mapping in self.issuer.iter().chain(client) {
+            if !mapping.applies_to(target)
+                || !mapping.allowed_by(scope)
+                || is_reserved_claim(&mapping.name)
+            {
+                continue;
+            }
+            if let Some(value) = mapping.resolve(user, &self.groups, client_id) {
+                insert_nested(&mut claims, &mapping.name, value);
+            }
+        }
+        claims
+    }
+}
+
+/// Insert a claim, creating the objects of a nested name
+fn insert_nested(claims: &mut Map<String, Value>, name: &str, value: Value) {
+    match name.split_once('.') {
+        None => {
+            claims.insert(name.to_string(), value);
+        }
+        Some((parent, rest)) => {
+            let parent = claims
+                .entry(parent)
+                .and_modify(|parent| {
+                    if !parent.is_object() {
+                        *parent = Value::Object(Map::new());
+                    }
+                })
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(parent) = parent {
+                insert_nested(parent, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::issuer::ClientSettings;
+    use serde_json::json;
+
+    #[test]
+    fn mapping() {
+        let mappings: Vec<ClaimMapping> = serde_json::from_value(json!([
+            {"name": "tenant", "value": {"static": "acme"}},
+            {"name": "email", "value": {"attribute": "email"}},
+            {"name": "phone_number", "value": {"attribute": "phone"}},
+            {"name": "realm_access.roles", "value": {"attribute": "roles"}, "targets": ["accessToken"]},
+            {"name": "sub", "value": {"static": "someone-else"}},
+            {"name": "cnf.jkt", "value": {"static": "forged"}},
+        ]))
+        .unwrap();
+        let client = Client::Public {
+            id: "frontend".into(),
+            redirect_urls: vec![],
+            default_scope: "openid".into(),
+            settings: ClientSettings {
+                claims: serde_json::from_value(json!([
+                    {"name": "tenant", "value": {"static": "frontend"}},
+                ]))
+                .unwrap(),
+                ..Default::default()
+            },
+        };
+        let users = vec![User {
+            attributes: serde_json::from_value(json!({
+                "email": "marvin@example.com",
+                "roles": ["admin", "user"],
+            }))
+            .unwrap(),
+            ..User::new("Marvin")
+        }];
+        let policy = ClaimsPolicy::new(mappings, &[client], Arc::new(users), vec![]);
+
+        assert_eq!(
+            Value::Object(policy.claims(ClaimTarget::AccessToken, "Marvin", None, None)),
+            json!({
+                "tenant": "acme",
+                "email": "marvin@example.com",
+                "realm_access": {"roles": ["admin", "user"]},
+            })
+        );
+        assert_eq!(
+            Value::Object(policy.claims(ClaimTarget::IdToken, "Marvin", Some("frontend"), None)),
+            json!({
+                "tenant": "frontend",
+                "email": "marvin@example.com",
+            })
+        );
+        assert_eq!(
+            Value::Object(policy.claims(ClaimTarget::Userinfo, "unknown", None, None)),
+            json!({"tenant": "acme"})
+        );
+    }
+
+    #[test]
+    fn groups_and_roles() {
+        let mappings: Vec<ClaimMapping> = serde_json::from_value(json!([
+            {"name": "groups", "value": "groups", "targets": ["accessToken", "idToken"]},
+            {"name": "roles", "value": "roles", "scope": "roles"},
+            {"name": "resource_access.api.roles", "value": {"clientRoles": "api"}, "targets": ["accessToken"]},
+        ]))
+        .unwrap();
+        let groups: Vec<Group> = serde_json::from_value(json!([
+            {"id": "admins", "groups": ["staff"], "roles": {"api": ["write"]}},
+            {"id": "staff", "roles": {"frontend": ["user"], "api": ["read"]}},
+        ]))
+        .unwrap();
+        let users: Vec<User> = serde_json::from_value(json!([
+            {"id": "Marvin", "groups": ["admins"], "roles": {"frontend": ["admin"]}},
+        ]))
+        .unwrap();
+        let policy = ClaimsPolicy::new(mappings, &[], Arc::new(users), groups);
+        let scope = "openid roles".parse().unwrap();
+
+        assert_eq!(
+            Value::Object(policy.claims(
+                ClaimTarget::AccessToken,
+                "Marvin",
+                Some("frontend"),
+                Some(&scope)
+            )),
+            json!({
+                "groups": ["admins", "staff"],
+                "roles": ["admin", "user"],
+                "resource_access": {"api": {"roles": ["write", "read"]}},
+            })
+        );
+        assert_eq!(
+            Value::Object(policy.claims(
+                ClaimTarget::IdToken,
+                "Marvin",
+                Some("frontend"),
+                Some(&"openid".parse().unwrap())
+            )),
+            json!({"groups": ["admins", "staff"]})
+        );
+        assert_eq!(
+            Value::Object(policy.claims(ClaimTarget::Userinfo, "Marvin", Some("frontend"), None)),
+            json!({})
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// How long the user has to decide on a consent request
+pub const PENDING_CONSENT_LIFETIME: Duration = Duration::minutes(10);
+
+/// A consent of a user, remembered for a client
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RememberedConsent {
+    pub subject: String,
+    pub client_id: String,
+    pub scopes: BTreeSet<String>,
+}
+
+/// An authorization request waiting for the consent of the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingConsent {
+    /// The login session the consent was requested for
+    pub session: String,
+    pub subject: String,
+    pub client_id: String,
+    pub scopes: BTreeSet<String>,
+    /// The query of the original authorization request
+    pub query: String,
+    /// Where to deliver a denial, taken from the request rather than from the query, which may
+    /// only refer to a pushed request or a request object
+    pub redirect_uri: Option<String>,
+    pub state: Option<String>,
+    pub response_type: String,
+    pub response_mode: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct InnerConsents {
+    /// Remembered consents, by subject and client
+    granted: BTreeMap<(String, String), BTreeSet<String>>,
+    /// Pending consents and when they expire, by ticket
+    pending: HashMap<String, (PendingConsent, DateTime<Utc>)>,
+}
+
+/// The consents of the users of an issuer
+#[derive(Clone, Debug, Default)]
+pub struct Consents(Arc<Mutex<InnerConsents>>);
+
+impl Consents {
+    /// Check if the user consented to all scopes for the client
+    pub fn covers<'a>(
+        &self,
+        subject: &str,
+        client_id: &str,
+        scopes: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        let inner = self.lock();
+        let granted = inner
+            .granted
+            .get(&(subject.to_string(), client_id.to_string()));
+        scopes
+            .into_iter()
+            .all(|scope| granted.is_some_and(|granted| granted.contains(scope)))
+    }
+
+    /// Remember the consent to additional scopes
+    pub fn grant(&self, subject: &str, client_id: &str, scopes: impl IntoIterator<Item = String>) {
+        self.lock()
+            .granted
+            .entry((subject.to_string(), client_id.to_string()))
+            .or_default()
+            .extend(scopes);
+    }
+
+    /// Forget a consent, returning whether there was one
+    pub fn revoke(&self, subject: &str, client_id: &str) -> bool {
+        self.lock()
+            .granted
+            .remove(&(subject.to_string(), client_id.to_string()))
+            .is_some()
+    }
+
+    /// List the remembered consents of a user
+    pub fn list(&self, subject: &str) -> Vec<RememberedConsent> {
+        self.lock()
+            .granted
+            .iter()
+            .filter(|((s, _), _)| s == subject)
+            .map(|((subject, client_id), scopes)| RememberedConsent {
+                subject: subject.clone(),
+                client_id: client_id.clone(),
+                scopes: scopes.clone(),
+            })
+            .collect()
+    }
+
+    /// Store a pending consent, returning the ticket for it
+    pub fn request(&self, pending: PendingConsent) -> String {
+        let ticket = uuid::Uuid::new_v4().to_string();
+
+        let mut inner = self.lock();
+        let now = Utc::now();
+        inner.pending.retain(|_, (_, expires)| *expires > now);
+        inner
+            .pending
+            .insert(ticket.clone(), (pending, now + PENDING_CONSENT_LIFETIME));
+
+        ticket
+    }
+
+    /// Take a pending consent which hasn't expired yet, a ticket can only be used once
+    pub fn take_pending(&self, ticket: &str) -> Option<PendingConsent> {
+        self.lock()
+            .pending
+            .remove(ticket)
+            .filter(|(_, expires)| *expires > Utc::now())
+            .map(|(pending, _)| pending)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InnerConsents> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pending() -> PendingConsent {
+        PendingConsent {
+            session: "session".into(),
+            subject: "Marvin".into(),
+            client_id: "partner".into(),
+            scopes: BTreeSet::from(["openid".to_string()]),
+            query: String::new(),
+            redirect_uri: None,
+            state: None,
+            response_type: "code".into(),
+            response_mode: None,
+        }
+    }
+
+    #[test]
+    fn pending_expiry() {
+        let consents = Consents::default();
+        let ticket = consents.request(pending());
+        assert_eq!(consents.take_pending(&ticket), Some(pending()));
+        assert_eq!(consents.take_pending(&ticket), None);
+
+        let expired = consents.request(pending());
+        consents.lock().pending.get_mut(&expired).unwrap().1 = Utc::now();
+
+        // expired tickets are removed when requesting new ones
+        let ticket = consents.request(pending());
+        assert_eq!(consents.lock().pending.len(), 1);
+        assert_eq!(consents.take_pending(&expired), None);
+        assert!(consents.take_pending(&ticket).is_some());
+    }
+}
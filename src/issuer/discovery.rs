@@ -0,0 +1,293 @@
+use super::{Client, Issuer, IssuerState, TlsClientAuth};
+use crate::endpoints::Error;
+use openidconnect::{
+    AuthUrl, EndSessionUrl, IssuerUrl, JsonWebKeySetUrl, LogoutProviderMetadata, ResponseTypes,
+    TokenUrl, UserInfoUrl,
+    core::{
+        CoreAuthDisplay, CoreClaimName, CoreClaimType, CoreClientAuthMethod, CoreGrantType,
+        CoreJsonWebKey, CoreJweContentEncryptionAlgorithm, CoreJweKeyManagementAlgorithm,
+        CoreJwsSigningAlgorithm, CoreResponseMode, CoreResponseType, CoreSubjectIdentifierType,
+    },
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The claims we know how to fill
+const CLAIMS_SUPPORTED: &[&str] = &[
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "azp",
+    "auth_time",
+    "nonce",
+    "sid",
+    "at_hash",
+    "c_hash",
+    "scope",
+    "preferred_username",
+];
+
+/// The algorithms we can verify request objects with
+const REQUEST_OBJECT_SIGNING_ALGS: &[CoreJwsSigningAlgorithm] = &[
+    CoreJwsSigningAlgorithm::HmacSha256,
+    CoreJwsSigningAlgorithm::HmacSha384,
+    CoreJwsSigningAlgorithm::HmacSha512,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha384,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha512,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha256,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha384,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha512,
+    CoreJwsSigningAlgorithm::EcdsaP256Sha256,
+    CoreJwsSigningAlgorithm::EcdsaP384Sha384,
+];
+
+/// The algorithms we can verify DPoP proofs with
+const DPOP_SIGNING_ALGS: &[CoreJwsSigningAlgorithm] = &[
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha384,
+    CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha512,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha256,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha384,
+    CoreJwsSigningAlgorithm::RsaSsaPssSha512,
+    CoreJwsSigningAlgorithm::EcdsaP256Sha256,
+    CoreJwsSigningAlgorithm::EcdsaP384Sha384,
+];
+
+/// Provider metadata which isn't part of OpenID Connect discovery, but of other specs
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdditionalProviderMetadata {
+    /// RFC 7009
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<Url>,
+    /// RFC 7009
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revocation_endpoint_auth_methods_supported: Vec<CoreClientAuthMethod>,
+    /// RFC 7636
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code_challenge_methods_supported: Vec<String>,
+    /// OpenID Connect Back-Channel Logout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backchannel_logout_supported: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backchannel_logout_session_supported: Option<bool>,
+    /// OpenID Connect Front-Channel Logout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontchannel_logout_supported: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontchannel_logout_session_supported: Option<bool>,
+    /// RFC 9126
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_authorization_request_endpoint: Option<Url>,
+    /// OpenID Connect Session Management
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_session_iframe: Option<Url>,
+    /// JWT Secured Authorization Response Mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authorization_signing_alg_values_supported: Vec<CoreJwsSigningAlgorithm>,
+    /// RFC 9449
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dpop_signing_alg_values_supported: Vec<CoreJwsSigningAlgorithm>,
+    /// RFC 8705
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_client_certificate_bound_access_tokens: Option<bool>,
+}
+
+impl openidconnect::AdditionalProviderMetadata for AdditionalProviderMetadata {}
+
+pub type ProviderMetadata = openidconnect::ProviderMetadata<
+    LogoutProviderMetadata<AdditionalProviderMetadata>,
+    CoreAuthDisplay,
+    CoreClientAuthMethod,
+    CoreClaimName,
+    CoreClaimType,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJsonWebKey,
+    CoreResponseMode,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+/// What an issuer actually supports, based on its configuration
+///
+/// The discovery document is generated from this, so that it matches the behavior.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub grant_types: Vec<CoreGrantType>,
+    pub response_types: Vec<ResponseTypes<CoreResponseType>>,
+    pub response_modes: Vec<CoreResponseMode>,
+    pub client_auth_methods: Vec<CoreClientAuthMethod>,
+    pub code_challenge_methods: Vec<String>,
+    pub certificate_bound_access_tokens: bool,
+}
+
+impl Capabilities {
+    pub fn new(issuer: &Issuer) -> Self {
+        let confidential = issuer
+            .clients
+            .iter()
+            .any(|client| matches!(client, Client::Confidential { .. }));
+        let public = issuer
+            .clients
+            .iter()
+            .any(|client| matches!(client, Client::Public { .. }));
+
+        let mut grant_types = vec![
+            CoreGrantType::AuthorizationCode,
+            CoreGrantType::RefreshToken,
+        ];
+        let implicit = issuer
+            .clients
+            .iter()
+            .any(|client| client.settings().allow_implicit);
+
+        let mut client_auth_methods = vec![];
+        let mut response_types = vec![ResponseTypes::new(vec![CoreResponseType::Code])];
+
+        if confidential {
+            grant_types.push(CoreGrantType::ClientCredentials);
+            client_auth_methods.extend([
+                CoreClientAuthMethod::ClientSecretBasic,
+                CoreClientAuthMethod::ClientSecretPost,
+            ]);
+        }
+        if public {
+            client_auth_methods.push(CoreClientAuthMethod::None);
+        }
+        for method in issuer
+            .clients
+            .iter()
+            .filter_map(|client| client.settings().tls_client_auth.as_ref())
+            .map(TlsClientAuth::method)
+        {
+            if !client_auth_methods.contains(&method) {
+                client_auth_methods.push(method);
+            }
+        }
+        if implicit {
+            grant_types.push(CoreGrantType::Implicit);
+            response_types.extend(
+                [
+                    &[CoreResponseType::Token][..],
+                    &[CoreResponseType::IdToken],
+                    &[CoreResponseType::IdToken, CoreResponseType::Token],
+                    &[CoreResponseType::Code, CoreResponseType::IdToken],
+                    &[CoreResponseType::Code, CoreResponseType::Token],
+                    &[
+                        CoreResponseType::Code,
+                        CoreResponseType::IdToken,
+                        CoreResponseType::Token,
+                    ],
+                ]
+                .map(|types| ResponseTypes::new(types.to_vec())),
+            );
+        }
+
+        Self {
+            grant_types,
+            response_types,
+            response_modes: vec![
+                CoreResponseMode::Query,
+                CoreResponseMode::Fragment,
+                CoreResponseMode::FormPost,
+                CoreResponseMode::Extension("jwt".into()),
+                CoreResponseMode::Extension("query.jwt".into()),
+                CoreResponseMode::Extension("fragment.jwt".into()),
+                CoreResponseMode::Extension("form_post.jwt".into()),
+            ],
+            client_auth_methods,
+            // only S256, as plain isn't enabled
+            code_challenge_methods: vec!["S256".into()],
+            certificate_bound_access_tokens: issuer
+                .clients
+                .iter()
+                .any(|client| client.settings().tls_client_certificate_bound_access_tokens),
+        }
+    }
+}
+
+impl IssuerState {
+    pub async fn discovery(&self, base: Url) -> Result<ProviderMetadata, Error> {
+        let scopes = self
+            .inner
+            .read()
+            .await
+            .endpoint
+            .inner
+            .scopes
+            .iter()
+            .map(|scope| oauth2::Scope::new(scope.to_string()))
+            .collect();
+
+        let build = {
+            let base = base.clone();
+            move |segment| {
+                let mut url = base.clone();
+                url.path_segments_mut()
+                    .map_err(|()| Error::Url)?
+                    .push(segment);
+                Ok::<Url, Error>(url)
+            }
+        };
+
+        let capabilities = &self.capabilities;
+
+        let issuer = IssuerUrl::from_url(base);
+        let authorization_endpoint = AuthUrl::from_url(build("auth")?);
+        let jwks_uri = JsonWebKeySetUrl::from_url(build("keys")?);
+
+        let subject_types_supported = vec![CoreSubjectIdentifierType::Public];
+        let id_token_signing_alg_values_supported = vec![self.key.core_alg()];
+        let additional_metadata = LogoutProviderMetadata {
+            end_session_endpoint: Some(EndSessionUrl::from_url(build("logout")?)),
+            additional_metadata: AdditionalProviderMetadata {
+                revocation_endpoint: Some(build("revoke")?),
+                revocation_endpoint_auth_methods_supported: capabilities
+                    .client_auth_methods
+                    .clone(),
+                code_challenge_methods_supported: capabilities.code_challenge_methods.clone(),
+                backchannel_logout_supported: Some(true),
+                backchannel_logout_session_supported: Some(true),
+                frontchannel_logout_supported: Some(true),
+                frontchannel_logout_session_supported: Some(true),
+                check_session_iframe: Some(build("check_session")?),
+                pushed_authorization_request_endpoint: Some(build("par")?),
+                authorization_signing_alg_values_supported: vec![self.key.core_alg()],
+                dpop_signing_alg_values_supported: DPOP_SIGNING_ALGS.to_vec(),
+                tls_client_certificate_bound_access_tokens: capabilities
+                    .certificate_bound_access_tokens
+                    .then_some(true),
+            },
+        };
+
+        Ok(ProviderMetadata::new(
+            issuer,
+            authorization_endpoint,
+            jwks_uri,
+            capabilities.response_types.clone(),
+            subject_types_supported,
+            id_token_signing_alg_values_supported,
+            additional_metadata,
+        )
+        .set_token_endpoint(Some(TokenUrl::from_url(build("token")?)))
+        .set_token_endpoint_auth_methods_supported(Some(capabilities.client_auth_methods.clone()))
+        .set_scopes_supported(Some(scopes))
+        .set_grant_types_supported(Some(capabilities.grant_types.clone()))
+        .set_response_modes_supported(Some(capabilities.response_modes.clone()))
+        .set_claims_supported(Some(
+            CLAIMS_SUPPORTED
+                .iter()
+                .map(|claim| CoreClaimName::new(claim.to_string()))
+                .collect(),
+        ))
+        .set_request_parameter_supported(Some(true))
+        .set_request_uri_parameter_supported(Some(true))
+        .set_require_request_uri_registration(Some(true))
+        .set_request_object_signing_alg_values_supported(Some(REQUEST_OBJECT_SIGNING_ALGS.to_vec()))
+        .set_userinfo_endpoint(Some(UserInfoUrl::from_url(build("userinfo")?))))
+    }
+}
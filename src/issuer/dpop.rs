@@ -0,0 +1,51 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The DPoP state of an issuer (RFC 9449), the nonces handed out and the proofs seen
+#[derive(Clone, Debug, Default)]
+pub struct Dpop(Arc<Mutex<DpopState>>);
+
+#[derive(Debug, Default)]
+struct DpopState {
+    /// Nonces handed out to clients, with their expiry
+    nonces: HashMap<String, DateTime<Utc>>,
+    /// The `jti` of proofs which were used, until they would be rejected as too old anyway
+    proofs: HashMap<String, DateTime<Utc>>,
+}
+
+impl Dpop {
+    /// Create a new nonce, clients must include it in their proofs
+    pub fn nonce(&self, lifetime: Duration) -> String {
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+
+        let mut state = self.lock();
+        let now = Utc::now();
+        state.nonces.retain(|_, expires| *expires > now);
+        state.nonces.insert(nonce.clone(), now + lifetime);
+
+        nonce
+    }
+
+    /// Check if a nonce was handed out, and didn't expire yet
+    pub fn valid_nonce(&self, nonce: &str) -> bool {
+        self.lock()
+            .nonces
+            .get(nonce)
+            .is_some_and(|expires| *expires > Utc::now())
+    }
+
+    /// Record the use of a proof, returning `false` if it was used before
+    pub fn use_proof(&self, jti: &str, expires: DateTime<Utc>) -> bool {
+        let mut state = self.lock();
+        let now = Utc::now();
+        state.proofs.retain(|_, expires| *expires > now);
+        state.proofs.insert(jti.to_string(), expires).is_none()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DpopState> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,118 @@
+use super::User;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A group of users, which can be a member of other groups
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    /// The groups this group is a member of, its members are members of those groups too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Roles of the members, per client
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<String>>,
+}
+
+impl Group {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            groups: Default::default(),
+            roles: Default::default(),
+        }
+    }
+}
+
+/// All groups reachable from the given ones, following the membership of groups
+///
+/// The given groups come first, unknown groups are kept as they are. Cycles are tolerated.
+pub fn transitive_groups<'a>(
+    direct: impl IntoIterator<Item = &'a str>,
+    groups: &'a [Group],
+) -> Vec<&'a str> {
+    let mut result: Vec<&str> = vec![];
+    let mut pending = direct.into_iter().collect::<Vec<_>>();
+    pending.reverse();
+
+    while let Some(id) = pending.pop() {
+        if result.contains(&id) {
+            continue;
+        }
+        result.push(id);
+        if let Some(group) = groups.iter().find(|group| group.id == id) {
+            pending.extend(group.groups.iter().rev().map(String::as_str));
+        }
+    }
+
+    result
+}
+
+impl User {
+    /// The groups of the user, including the ones it is a member of through other groups
+    pub fn effective_groups<'a>(&'a self, groups: &'a [Group]) -> Vec<&'a str> {
+        transitive_groups(self.groups.iter().map(String::as_str), groups)
+    }
+
+    /// The roles of the user for a client, including the ones of its groups
+    pub fn client_roles<'a>(&'a self, client_id: &str, groups: &'a [Group]) -> Vec<&'a str> {
+        let inherited = self
+            .effective_groups(groups)
+            .into_iter()
+            .filter_map(|id| groups.iter().find(|group| group.id == id))
+            .filter_map(|group| group.roles.get(client_id));
+
+        let mut roles: Vec<&str> = vec![];
+        for role in self
+            .roles
+            .get(client_id)
+            .into_iter()
+            .chain(inherited)
+            .flatten()
+        {
+            if !roles.contains(&role.as_str()) {
+                roles.push(role);
+            }
+        }
+        roles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_groups() {
+        let groups = vec![
+            Group {
+                groups: vec!["staff".into()],
+                roles: BTreeMap::from([("app".into(), vec!["write".into()])]),
+                ..Group::new("admins")
+            },
+            Group {
+                // cycles must not hang
+                groups: vec!["admins".into(), "everyone".into()],
+                roles: BTreeMap::from([("app".into(), vec!["read".into(), "write".into()])]),
+                ..Group::new("staff")
+            },
+            Group::new("everyone"),
+        ];
+        let user = User {
+            groups: vec!["admins".into()],
+            roles: BTreeMap::from([("app".into(), vec!["owner".into()])]),
+            ..User::new("Marvin")
+        };
+
+        assert_eq!(
+            user.effective_groups(&groups),
+            vec!["admins", "staff", "everyone"]
+        );
+        assert_eq!(
+            user.client_roles("app", &groups),
+            vec!["owner", "write", "read"]
+        );
+        assert!(user.client_roles("other", &groups).is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+use super::{Client, JwtAccessGenerator};
+use chrono::{DateTime, Duration, Utc};
+use oxide_auth::primitives::{
+    generator::RandomGenerator,
+    grant::Grant,
+    issuer::RefreshedToken,
+    prelude::{AuthMap, Authorizer, IssuedToken, Issuer, TokenMap},
+};
+use std::{collections::HashMap, num::NonZeroU32, sync::Arc};
+
+/// Lifetime of access tokens, unless configured otherwise, the same as oxide-auth uses
+const DEFAULT_ACCESS_TOKEN: Duration = Duration::hours(1);
+/// Lifetime of ID tokens, unless configured otherwise
+const DEFAULT_ID_TOKEN: Duration = Duration::minutes(10);
+/// Lifetime of refresh tokens, unless configured otherwise, the one of the access tokens
+const DEFAULT_REFRESH_TOKEN: Duration = DEFAULT_ACCESS_TOKEN;
+/// Lifetime of authorization codes, unless configured otherwise, the same as oxide-auth uses
+const DEFAULT_AUTHORIZATION_CODE: Duration = Duration::minutes(10);
+
+/// Lifetimes of the issued tokens, in seconds
+///
+/// Configured for an issuer, and overridden per client. Anything not configured falls back to
+/// the issuer, and then to the defaults.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Lifetimes {
+    /// Lifetime of access tokens, defaults to 3600 seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<NonZeroU32>,
+    /// Lifetime of ID tokens, defaults to 600 seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<NonZeroU32>,
+    /// Lifetime of refresh tokens, defaults to 3600 seconds
+    ///
+    /// A refreshed token gets a new refresh token, valid for the full lifetime again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<NonZeroU32>,
+    /// Lifetime of authorization codes, defaults to 600 seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authorization_code: Option<NonZeroU32>,
+}
+
+impl Lifetimes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill the lifetimes not configured with the ones of `fallback`
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            access_token: self.access_token.or(fallback.access_token),
+            id_token: self.id_token.or(fallback.id_token),
+            refresh_token: self.refresh_token.or(fallback.refresh_token),
+            authorization_code: self.authorization_code.or(fallback.authorization_code),
+        }
+    }
+
+    pub fn access_token(&self) -> Duration {
+        seconds(self.access_token).unwrap_or(DEFAULT_ACCESS_TOKEN)
+    }
+
+    pub fn id_token(&self) -> Duration {
+        seconds(self.id_token).unwrap_or(DEFAULT_ID_TOKEN)
+    }
+
+    pub fn refresh_token(&self) -> Duration {
+        seconds(self.refresh_token).unwrap_or(DEFAULT_REFRESH_TOKEN)
+    }
+
+    pub fn authorization_code(&self) -> Duration {
+        seconds(self.authorization_code).unwrap_or(DEFAULT_AUTHORIZATION_CODE)
+    }
+}
+
+fn seconds(value: Option<NonZeroU32>) -> Option<Duration> {
+    value.map(|value| Duration::seconds(value.get().into()))
+}
+
+/// The lifetimes of an issuer, with the overrides of its clients
+#[derive(Clone, Debug, Default)]
+pub struct LifetimePolicy {
+    issuer: Lifetimes,
+    clients: Arc<HashMap<String, Lifetimes>>,
+}
+
+impl LifetimePolicy {
+    pub fn new(issuer: Lifetimes, clients: &[Client]) -> Self {
+        let clients = clients
+            .iter()
+            .map(|client| (client.id().to_string(), client.settings().lifetimes))
+            .collect();
+
+        Self {
+            issuer,
+            clients: Arc::new(clients),
+        }
+    }
+
+    /// The lifetimes of the issuer, for tokens not issued to any client
+    pub fn issuer(&self) -> Lifetimes {
+        self.issuer
+    }
+
+    /// The lifetimes of tokens issued to a client
+    pub fn client(&self, client_id: &str) -> Lifetimes {
+        self.clients
+            .get(client_id)
+            .copied()
+            .unwrap_or_default()
+            .or(self.issuer)
+    }
+}
+
+/// Issues authorization codes, valid for the lifetime configured for the client
+pub struct Codes {
+    inner: AuthMap<RandomGenerator>,
+    lifetimes: LifetimePolicy,
+}
+
+impl Codes {
+    pub fn new(lifetimes: LifetimePolicy) -> Self {
+        Self {
+            inner: AuthMap::new(RandomGenerator::new(16)),
+            lifetimes,
+        }
+    }
+}
+
+impl Authorizer for Codes {
+    fn authorize(&mut self, mut grant: Grant) -> Result<String, ()> {
+        grant.until = Utc::now() + self.lifetimes.client(&grant.client_id).authorization_code();
+        self.inner.authorize(grant)
+    }
+
+    fn extract(&mut self, code: &str) -> Result<Option<Grant>, ()> {
+        self.inner.extract(code)
+    }
+}
+
+/// Issues access and refresh tokens, valid for the lifetimes configured for the client
+///
+/// oxide-auth uses the expiry of the access token for the refresh token as well, so a refresh
+/// token would become useless as soon as its access token expired. The expiry of refresh tokens
+/// is tracked separately instead.
+pub struct Tokens {
+    inner: TokenMap<JwtAccessGenerator>,
+    lifetimes: LifetimePolicy,
+    /// The expiry of each refresh token, along with the access token issued with it
+    refresh_expiry: HashMap<String, (String, DateTime<Utc>)>,
+}
+
+impl Tokens {
+    pub fn new(generator: JwtAccessGenerator, lifetimes: LifetimePolicy) -> Self {
+        Self {
+            inner: TokenMap::new(generator.with_lifetimes(lifetimes.clone())),
+            lifetimes,
+            refresh_expiry: Default::default(),
+        }
+    }
+
+    /// Revoke an access or refresh token
+    ///
+    /// Both tokens issued together are revoked.
+    pub fn revoke(&mut self, token: &str) {
+        self.inner.revoke(token);
+        self.refresh_expiry
+            .retain(|refresh, (access, _)| refresh != token && access != token);
+    }
+
+    fn track_refresh(&mut self, access: &str, refresh: Option<&String>, client_id: &str) {
+        if let Some(refresh) = refresh {
+            let now = Utc::now();
+            self.refresh_expiry.retain(|_, (_, until)| *until > now);
+
+            let until = now + self.lifetimes.client(client_id).refresh_token();
+            self.refresh_expiry
+                .insert(refresh.clone(), (access.to_string(), until));
+        }
+    }
+}
+
+impl Issuer for Tokens {
+    fn issue(&mut self, mut grant: Grant) -> Result<IssuedToken, ()> {
+        let client_id = grant.client_id.clone();
+        grant.until = Utc::now() + self.lifetimes.client(&client_id).access_token();
+
+        let issued = self.inner.issue(grant)?;
+        self.track_refresh(&issued.token, issued.refresh.as_ref(), &client_id);
+        Ok(issued)
+    }
+
+    fn refresh(&mut self, refresh: &str, mut grant: Grant) -> Result<RefreshedToken, ()> {
+        let client_id = grant.client_id.clone();
+        grant.until = Utc::now() + self.lifetimes.client(&client_id).access_token();
+
+        let refreshed = self.inner.refresh(refresh, grant)?;
+        self.refresh_expiry.remove(refresh);
+        self.track_refresh(&refreshed.token, refreshed.refresh.as_ref(), &client_id);
+        Ok(refreshed)
+    }
+
+    fn recover_token<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        self.inner.recover_token(token)
+    }
+
+    /// Recover the grant of a refresh token, which expires with the refresh token
+    fn recover_refresh<'a>(&'a self, token: &'a str) -> Result<Option<Grant>, ()> {
+        let Some((_, until)) = self.refresh_expiry.get(token).cloned() else {
+            return Ok(None);
+        };
+        if until <= Utc::now() {
+            return Ok(None);
+        }
+
+        Ok(self
+            .inner
+            .recover_refresh(token)?
+            .map(|grant| Grant { until, ..grant }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{extensions::ConnectionInformation, issuer::ClientSettings, secrets::Key};
+    use oxide_auth::primitives::grant::Extensions;
+    use serde_json::json;
+
+    #[test]
+    fn client_overrides_issuer() {
+        let issuer: Lifetimes = serde_json::from_value(json!({
+            "accessToken": 300,
+            "refreshToken": 3600,
+        }))
+        .unwrap();
+        let client = Client::Confidential {
+            id: "client".into(),
+            secret: "secret".to_string().into(),
+            default_scope: "openid".into(),
+            settings: ClientSettings {
+                lifetimes: serde_json::from_value(json!({"accessToken": 30})).unwrap(),
+                ..Default::default()
+            },
+        };
+        let policy = LifetimePolicy::new(issuer, &[client]);
+
+        let lifetimes = policy.client("client");
+        assert_eq!(lifetimes.access_token(), Duration::seconds(30));
+        assert_eq!(lifetimes.refresh_token(), Duration::seconds(3600));
+        assert_eq!(lifetimes.id_token(), DEFAULT_ID_TOKEN);
+
+        let lifetimes = policy.client("other");
+        assert_eq!(lifetimes.access_token(), Duration::seconds(300));
+
+        assert!(serde_json::from_value::<Lifetimes>(json!({"idToken": 0})).is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        // the lifetimes used before they could be configured
+        let lifetimes = LifetimePolicy::default().client("client");
+        assert_eq!(lifetimes.access_token(), Duration::hours(1));
+        assert_eq!(lifetimes.id_token(), Duration::minutes(10));
+        assert_eq!(lifetimes.refresh_token(), Duration::hours(1));
+        assert_eq!(lifetimes.authorization_code(), Duration::minutes(10));
+    }
+
+    #[test]
+    fn refresh_expiry() -> anyhow::Result<()> {
+        let key = Key::new("key", "this-is-not-a-secret-test-key-0123456789");
+        let mut tokens = Tokens::new(
+            JwtAccessGenerator::new("/test".into(), key),
+            LifetimePolicy::default(),
+        );
+        let conn = ConnectionInformation(
+            actix_web::test::TestRequest::default()
+                .to_http_request()
+                .connection_info()
+                .clone(),
+        );
+        let grant = || -> anyhow::Result<Grant> {
+            let mut extensions = Extensions::new();
+            extensions.set(&conn, conn.encode());
+            Ok(Grant {
+                owner_id: "Marvin".into(),
+                client_id: "client".into(),
+                scope: "openid".parse()?,
+                redirect_uri: "http://localhost/cb".parse()?,
+                until: Utc::now(),
+                extensions,
+            })
+        };
+        let tracked = |tokens: &Tokens| tokens.refresh_expiry.keys().cloned().collect::<Vec<_>>();
+
+        // revoking the access token revokes its refresh token as well
+        let issued = tokens
+            .issue(grant()?)
+            .map_err(|()| anyhow::anyhow!("issue"))?;
+        tokens.revoke(&issued.token);
+        assert!(tracked(&tokens).is_empty());
+
+        // a rotated refresh token is no longer tracked
+        let issued = tokens
+            .issue(grant()?)
+            .map_err(|()| anyhow::anyhow!("issue"))?;
+        let refreshed = tokens
+            .refresh(issued.refresh.as_deref().unwrap_or_default(), grant()?)
+            .map_err(|()| anyhow::anyhow!("refresh"))?;
+        assert_eq!(tracked(&tokens), Vec::from_iter(refreshed.refresh.clone()));
+
+        // expired refresh tokens are pruned when issuing new ones
+        for (_, until) in tokens.refresh_expiry.values_mut() {
+            *until = Utc::now();
+        }
+        let issued = tokens
+            .issue(grant()?)
+            .map_err(|()| anyhow::anyhow!("issue"))?;
+        assert_eq!(tracked(&tokens), Vec::from_iter(issued.refresh));
+
+        Ok(())
+    }
+}
@@ -1,20 +1,39 @@
+mod audience;
+mod claims;
+mod consent;
+mod discovery;
+mod dpop;
+mod group;
+mod lifetime;
+mod mtls;
+mod pushed;
 mod redirect_url;
+mod session;
 mod token;
-
+mod user;
+mod validate;
+
+pub use audience::*;
+pub use claims::*;
+pub use consent::*;
+pub use discovery::*;
+pub use dpop::*;
+pub use group::*;
+pub use lifetime::*;
+pub use mtls::*;
+pub use pushed::*;
 pub use redirect_url::*;
+pub use session::*;
 pub use token::*;
+pub use user::*;
+pub use validate::*;
 
-use crate::{endpoints::Error, secrets::Key};
+use crate::{endpoints::Error, metrics::UNKNOWN_CLIENT, oidc::AdditionalClaims, secrets::Key};
+use biscuit::{Empty, jwk::JWKSet};
 use hide::Hide;
 use openidconnect::{
-    AuthUrl, EmptyAdditionalClaims, EmptyAdditionalProviderMetadata, EndSessionUrl,
-    EndUserUsername, IssuerUrl, JsonWebKeySetUrl, LogoutProviderMetadata,
-    ProviderMetadataWithLogout, ResponseTypes, StandardClaims, SubjectIdentifier, TokenUrl,
-    UserInfoUrl,
-    core::{
-        CoreClientAuthMethod, CoreGrantType, CoreJsonWebKeySet, CoreResponseType,
-        CoreSubjectIdentifierType, CoreUserInfoClaims,
-    },
+    EndUserUsername, StandardClaims, SubjectIdentifier, UserInfoClaims,
+    core::{CoreGenderClaim, CoreJsonWebKeySet},
 };
 use oxide_auth::{
     frontends::simple::{
@@ -28,18 +47,11 @@ use oxide_auth::{
     },
 };
 use oxide_auth_actix::OAuthResponse;
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 use tokio::sync::RwLock;
 use url::Url;
 
-pub type Endpoint = Generic<
-    ClientMap,
-    AuthMap<RandomGenerator>,
-    TokenMap<JwtAccessGenerator>,
-    Vacant,
-    Vec<Scope>,
-    fn() -> OAuthResponse,
->;
+pub type Endpoint = Generic<ClientMap, Codes, Tokens, Vacant, Vec<Scope>, fn() -> OAuthResponse>;
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -50,6 +62,8 @@ pub enum Client {
         secret: Hide<String>,
         #[serde(default = "default::default_scope")]
         default_scope: String,
+        #[serde(flatten)]
+        settings: ClientSettings,
     },
     #[serde(rename_all = "camelCase")]
     Public {
@@ -59,9 +73,69 @@ pub enum Client {
         redirect_urls: Vec<RedirectUrl>,
         #[serde(default = "default::default_scope")]
         default_scope: String,
+        #[serde(flatten)]
+        settings: ClientSettings,
     },
 }
 
+/// Settings common to all types of clients
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientSettings {
+    /// Allow the implicit and hybrid flows, which return tokens from the authorization endpoint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_implicit: bool,
+    /// Ask the user to consent to the requested scopes, intended for third-party clients
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_consent: bool,
+    /// URLs the user may be redirected to after logging out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_logout_redirect_urls: Vec<Url>,
+    /// Notified with a logout token when the user logs out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backchannel_logout_uri: Option<Url>,
+    /// Loaded in an iframe of the logout page when the user logs out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frontchannel_logout_uri: Option<Url>,
+    /// Only accept authorization requests which were pushed to the PAR endpoint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_pushed_authorization_requests: bool,
+    /// Public keys of the client, used to verify the signature of its request objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<serde_json::Value>")]
+    pub jwks: Option<JWKSet<Empty>>,
+    /// URLs the request objects of the client may be fetched from, using the `request_uri` parameter
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub request_uris: Vec<Url>,
+    /// Only issue access tokens bound to a DPoP key, requiring a DPoP proof on the token endpoint
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dpop_bound_access_tokens: bool,
+    /// Require DPoP proofs to carry a nonce provided by the server
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_dpop_nonce: bool,
+    /// Authenticate the client using its TLS client certificate, instead of its secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_client_auth: Option<TlsClientAuth>,
+    /// Only issue access tokens bound to the TLS client certificate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tls_client_certificate_bound_access_tokens: bool,
+    /// Audiences of the access tokens issued to the client, unless it requests a resource
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audiences: Vec<String>,
+    /// Resources the client may request access tokens for, using the `resource` parameter
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<String>,
+    /// Claims added for the client, after the ones of the issuer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claims: Vec<ClaimMapping>,
+    /// Roles of the client, which users and groups can be assigned
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Lifetimes of the tokens issued to the client, overriding the ones of the issuer
+    #[serde(default, skip_serializing_if = "Lifetimes::is_empty")]
+    pub lifetimes: Lifetimes,
+}
+
 impl Client {
     pub fn id(&self) -> &str {
         match self {
@@ -69,6 +143,13 @@ impl Client {
             Client::Public { id, .. } => id,
         }
     }
+
+    pub fn settings(&self) -> &ClientSettings {
+        match self {
+            Client::Confidential { settings, .. } => settings,
+            Client::Public { settings, .. } => settings,
+        }
+    }
 }
 
 mod default {
@@ -93,8 +174,33 @@ pub struct KeyConfig(String);
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct Issuer {
     pub scopes: Vec<String>,
+    /// Descriptions of the scopes, shown to the user when asking for consent
+    #[serde(
+        default,
+        rename = "scopeDescriptions",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub scope_descriptions: BTreeMap<String, String>,
+    /// Audiences added to access tokens when the scope is granted
+    #[serde(
+        default,
+        rename = "scopeAudiences",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub scope_audiences: BTreeMap<String, Vec<String>>,
     pub clients: Vec<Client>,
+    #[serde(default = "default_users")]
+    pub users: Vec<User>,
+    /// Groups the users can be members of
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+    /// Claims added to the tokens and userinfo of the users
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claims: Vec<ClaimMapping>,
     pub key: KeyConfig,
+    /// Lifetimes of the issued tokens, unless overridden by the client
+    #[serde(default, skip_serializing_if = "Lifetimes::is_empty")]
+    pub lifetimes: Lifetimes,
 }
 
 impl Issuer {
@@ -105,8 +211,14 @@ impl Issuer {
     {
         Ok(Self {
             scopes: scopes.into_iter().map(|s| s.into()).collect(),
+            scope_descriptions: Default::default(),
+            scope_audiences: Default::default(),
             clients: Default::default(),
+            users: default_users(),
+            groups: Default::default(),
+            claims: Default::default(),
             key: KeyConfig(key.into()),
+            lifetimes: Default::default(),
         })
     }
 
@@ -115,8 +227,32 @@ impl Issuer {
         self
     }
 
+    /// Replace the default users with the provided ones
+    pub fn users(mut self, users: impl IntoIterator<Item = User>) -> Self {
+        self.users = users.into_iter().collect();
+        self
+    }
+
+    /// Set the groups the users can be members of
+    pub fn groups(mut self, groups: impl IntoIterator<Item = Group>) -> Self {
+        self.groups = groups.into_iter().collect();
+        self
+    }
+
+    /// Set the lifetimes of the issued tokens
+    pub fn lifetimes(mut self, lifetimes: Lifetimes) -> Self {
+        self.lifetimes = lifetimes;
+        self
+    }
+
     pub fn build(self, base: Url) -> Result<IssuerState, IssueBuildError> {
         let mut registrar = vec![];
+        let capabilities = Capabilities::new(&self);
+        let clients = self.clients.clone();
+        let lifetimes = LifetimePolicy::new(self.lifetimes, &self.clients);
+        let audiences = AudiencePolicy::new(self.scope_audiences, &self.clients);
+        let users = Arc::new(self.users);
+        let claims = ClaimsPolicy::new(self.claims, &self.clients, users.clone(), self.groups);
 
         for client in self.clients {
             match client {
@@ -124,6 +260,7 @@ impl Issuer {
                     id,
                     secret,
                     default_scope,
+                    ..
                 } => {
                     // for the confidential client we don't really need it
                     let url = RegisteredUrl::Semantic(Url::parse("http://localhost")?);
@@ -138,6 +275,7 @@ impl Issuer {
                     id,
                     redirect_urls,
                     default_scope,
+                    ..
                 } => {
                     let mut i = redirect_urls.into_iter();
                     let redirect_uri = i.next().ok_or(IssueBuildError::MissingRedirectUri)?;
@@ -157,8 +295,13 @@ impl Issuer {
         let endpoint = Extended {
             inner: Endpoint {
                 registrar: registrar.into_iter().collect(),
-                authorizer: AuthMap::new(RandomGenerator::new(16)),
-                issuer: TokenMap::new(JwtAccessGenerator::new(base.path().into(), key.clone())),
+                authorizer: Codes::new(lifetimes.clone()),
+                issuer: Tokens::new(
+                    JwtAccessGenerator::new(base.path().into(), key.clone())
+                        .with_audiences(audiences.clone())
+                        .with_claims(claims.clone()),
+                    lifetimes.clone(),
+                ),
                 solicitor: Vacant,
                 scopes: self
                     .scopes
@@ -172,6 +315,17 @@ impl Issuer {
 
         Ok(IssuerState {
             key,
+            capabilities: Arc::new(capabilities),
+            clients: Arc::new(clients),
+            lifetimes,
+            audiences,
+            claims,
+            users,
+            sessions: Default::default(),
+            consents: Default::default(),
+            pushed: Default::default(),
+            dpop: Default::default(),
+            scope_descriptions: Arc::new(self.scope_descriptions),
             inner: Arc::new(RwLock::new(InnerState { endpoint })),
         })
     }
@@ -180,77 +334,62 @@ impl Issuer {
 #[derive(Clone)]
 pub struct IssuerState {
     pub key: Key,
+    pub capabilities: Arc<Capabilities>,
+    pub clients: Arc<Vec<Client>>,
+    pub lifetimes: LifetimePolicy,
+    pub audiences: AudiencePolicy,
+    pub claims: ClaimsPolicy,
+    pub users: Arc<Vec<User>>,
+    pub sessions: Sessions,
+    pub consents: Consents,
+    pub pushed: PushedRequests,
+    pub dpop: Dpop,
+    pub scope_descriptions: Arc<BTreeMap<String, String>>,
     pub inner: Arc<RwLock<InnerState>>,
 }
 
 impl IssuerState {
-    pub fn keys(&self) -> Result<CoreJsonWebKeySet, Error> {
-        Ok(CoreJsonWebKeySet::new(vec![self.key.key()]))
+    /// Find a client by ID
+    pub fn client(&self, id: &str) -> Option<&Client> {
+        self.clients.iter().find(|client| client.id() == id)
     }
 
-    pub async fn discovery(&self, base: Url) -> Result<ProviderMetadataWithLogout, Error> {
-        let scopes = self
-            .inner
-            .read()
-            .await
-            .endpoint
-            .inner
-            .scopes
-            .iter()
-            .map(|scope| oauth2::Scope::new(scope.to_string()))
-            .collect();
-
-        let build = {
-            let base = base.clone();
-            move |segment| {
-                let mut url = base.clone();
-                url.path_segments_mut()
-                    .map_err(|()| Error::Url)?
-                    .push(segment);
-                Ok::<Url, Error>(url)
-            }
-        };
+    /// The label of a client in metrics
+    ///
+    /// Only registered clients get their own label, so that callers can't create an unbounded
+    /// number of series.
+    pub fn client_label<'a>(&self, client_id: &'a str) -> &'a str {
+        match self.client(client_id) {
+            Some(_) => client_id,
+            None => UNKNOWN_CLIENT,
+        }
+    }
 
-        let issuer = IssuerUrl::from_url(base);
-        let authorization_endpoint = AuthUrl::from_url(build("auth")?);
-        let jwks_uri = JsonWebKeySetUrl::from_url(build("keys")?);
-
-        let response_types_supported: Vec<_> =
-            vec![ResponseTypes::new(vec![CoreResponseType::Token])];
-        let subject_types_supported = vec![CoreSubjectIdentifierType::Public];
-        let id_token_signing_alg_values_supported = vec![self.key.core_alg()];
-        let additional_metadata = LogoutProviderMetadata {
-            end_session_endpoint: Some(EndSessionUrl::from_url(build("logout")?)),
-            additional_metadata: EmptyAdditionalProviderMetadata::default(),
-        };
+    /// Find a user by ID
+    pub fn user(&self, id: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.id == id)
+    }
 
-        Ok(ProviderMetadataWithLogout::new(
-            issuer,
-            authorization_endpoint,
-            jwks_uri,
-            response_types_supported,
-            subject_types_supported,
-            id_token_signing_alg_values_supported,
-            additional_metadata,
-        )
-        .set_token_endpoint(Some(TokenUrl::from_url(build("token")?)))
-        .set_token_endpoint_auth_methods_supported(Some(vec![
-            CoreClientAuthMethod::ClientSecretBasic,
-            CoreClientAuthMethod::ClientSecretPost,
-        ]))
-        .set_scopes_supported(Some(scopes))
-        .set_grant_types_supported(Some(vec![
-            CoreGrantType::ClientCredentials,
-            CoreGrantType::AuthorizationCode,
-        ]))
-        .set_userinfo_endpoint(Some(UserInfoUrl::from_url(build("userinfo")?))))
+    pub fn keys(&self) -> Result<CoreJsonWebKeySet, Error> {
+        Ok(CoreJsonWebKeySet::new(vec![self.key.key()]))
     }
 
-    pub fn userinfo(&self) -> CoreUserInfoClaims {
-        let subject = SubjectIdentifier::new("Marvin".into());
-        let claims = StandardClaims::new(subject)
-            .set_preferred_username(Some(EndUserUsername::new("Marvin".into())));
-        CoreUserInfoClaims::new(claims, EmptyAdditionalClaims::default())
+    /// The userinfo of a subject, as returned to a client for the granted scope
+    pub fn userinfo(
+        &self,
+        subject: &str,
+        client_id: Option<&str>,
+        scope: Option<&Scope>,
+    ) -> UserInfoClaims<AdditionalClaims, CoreGenderClaim> {
+        let additional = self
+            .claims
+            .claims(ClaimTarget::Userinfo, subject, client_id, scope);
+        // a mapped username replaces the default one
+        let username = (!additional.contains_key("preferred_username"))
+            .then(|| EndUserUsername::new(subject.into()));
+        let claims = StandardClaims::new(SubjectIdentifier::new(subject.into()))
+            .set_preferred_username(username);
+        UserInfoClaims::new(claims, AdditionalClaims { claims: additional })
     }
 }
 
@@ -262,10 +401,28 @@ pub struct InnerState {
 
 #[cfg(test)]
 mod test {
-    use crate::issuer::RedirectUrl;
+    use crate::issuer::{Issuer, RedirectUrl};
     use oxide_auth::primitives::registrar::RegisteredUrl;
     use serde_json::json;
 
+    #[test]
+    fn userinfo_username() -> anyhow::Result<()> {
+        let mut issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?;
+        let state = issuer.clone().build("http://localhost/test".parse()?)?;
+        let userinfo = serde_json::to_value(state.userinfo("Marvin", None, None))?;
+        assert_eq!(userinfo["preferred_username"], "Marvin");
+
+        issuer.claims = serde_json::from_value(json!([
+            {"name": "preferred_username", "value": {"static": "marvin@example.com"}},
+        ]))?;
+        let state = issuer.build("http://localhost/test".parse()?)?;
+        let userinfo = serde_json::to_string(&state.userinfo("Marvin", None, None))?;
+        assert_eq!(userinfo.matches("preferred_username").count(), 1);
+        assert!(userinfo.contains(r#""preferred_username":"marvin@example.com""#));
+
+        Ok(())
+    }
+
     #[test]
     fn test_redirect_serde() {
         let url: RedirectUrl = serde_json::from_value(json!({
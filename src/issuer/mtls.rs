@@ -0,0 +1,136 @@
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use biscuit::{Empty, jwk::JWKSet};
+use openidconnect::core::CoreClientAuthMethod;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+/// How a client authenticates using mutual TLS (RFC 8705)
+#[derive(
+    Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum TlsClientAuth {
+    /// `tls_client_auth`, a certificate issued by a trusted CA, for the expected subject
+    Pki(CertificateSubject),
+    /// `self_signed_tls_client_auth`, a certificate from the `x5c` of the client's keys
+    SelfSigned,
+}
+
+/// The subject a client certificate must be issued for, only one of them is checked
+#[derive(
+    Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum CertificateSubject {
+    /// The distinguished name of the subject, e.g. `CN=client,O=Example`
+    SubjectDn(String),
+    SanDns(String),
+    SanUri(String),
+    SanIp(IpAddr),
+    SanEmail(String),
+}
+
+impl TlsClientAuth {
+    pub fn method(&self) -> CoreClientAuthMethod {
+        match self {
+            Self::Pki(_) => CoreClientAuthMethod::Extension("tls_client_auth".into()),
+            Self::SelfSigned => {
+                CoreClientAuthMethod::Extension("self_signed_tls_client_auth".into())
+            }
+        }
+    }
+
+    /// Check if a DER encoded certificate authenticates the client
+    ///
+    /// The `trusted` flag tells if the certificate was issued by one of the trusted CAs.
+    pub fn authenticates(
+        &self,
+        certificate: &[u8],
+        trusted: bool,
+        jwks: Option<&JWKSet<Empty>>,
+    ) -> bool {
+        match self {
+            Self::Pki(subject) => trusted && subject.matches(certificate),
+            Self::SelfSigned => jwks
+                .into_iter()
+                .flat_map(|jwks| &jwks.keys)
+                .filter_map(|key| key.common.x509_chain.as_ref()?.first())
+                .any(|x5c| {
+                    BASE64_STANDARD
+                        .decode(x5c)
+                        .is_ok_and(|registered| registered == certificate)
+                }),
+        }
+    }
+}
+
+impl CertificateSubject {
+    /// Check if a DER encoded certificate was issued for the subject
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        let Ok((_, certificate)) = X509Certificate::from_der(certificate) else {
+            return false;
+        };
+
+        if let Self::SubjectDn(dn) = self {
+            return normalize_dn(&certificate.subject().to_string()) == normalize_dn(dn);
+        }
+
+        let Ok(Some(san)) = certificate.subject_alternative_name() else {
+            return false;
+        };
+        san.value
+            .general_names
+            .iter()
+            .any(|name| match (self, name) {
+                (Self::SanDns(expected), GeneralName::DNSName(name)) => {
+                    expected.eq_ignore_ascii_case(name)
+                }
+                (Self::SanUri(expected), GeneralName::URI(name)) => expected == name,
+                (Self::SanEmail(expected), GeneralName::RFC822Name(name)) => expected == name,
+                (Self::SanIp(expected), GeneralName::IPAddress(name)) => match expected {
+                    IpAddr::V4(ip) => ip.octets() == *name,
+                    IpAddr::V6(ip) => ip.octets() == *name,
+                },
+                _ => false,
+            })
+    }
+}
+
+/// The SHA-256 thumbprint of a DER encoded certificate, as used by the `x5t#S256` confirmation
+pub fn certificate_thumbprint(certificate: &[u8]) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(certificate))
+}
+
+/// Bring a distinguished name into a form which can be compared
+///
+/// The order of the attributes differs between the notations, and so does the whitespace.
+fn normalize_dn(dn: &str) -> Vec<(String, String)> {
+    let mut attributes = dn
+        .split(',')
+        .filter_map(|attribute| attribute.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_uppercase(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+    attributes.sort();
+    attributes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distinguished_name() {
+        assert_eq!(
+            normalize_dn("C=DE, O=Example, CN=client"),
+            normalize_dn("cn=client,o=Example,c=DE")
+        );
+        assert_ne!(
+            normalize_dn("CN=client, O=Example"),
+            normalize_dn("CN=client")
+        );
+    }
+}
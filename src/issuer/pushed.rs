@@ -0,0 +1,65 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The prefix of the `request_uri` of pushed authorization requests
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// An authorization request, pushed by a client (RFC 9126)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushedRequest {
+    /// The authenticated client which pushed the request
+    pub client_id: String,
+    /// The parameters of the authorization request
+    pub parameters: Vec<(String, String)>,
+    pub expires: DateTime<Utc>,
+}
+
+/// The pushed authorization requests of an issuer, by their `request_uri`
+#[derive(Clone, Debug, Default)]
+pub struct PushedRequests(Arc<Mutex<HashMap<String, PushedRequest>>>);
+
+impl PushedRequests {
+    /// Store a request, returning its `request_uri`
+    pub fn push(
+        &self,
+        client_id: impl Into<String>,
+        parameters: Vec<(String, String)>,
+        lifetime: Duration,
+    ) -> String {
+        let request_uri = format!("{REQUEST_URI_PREFIX}{}", uuid::Uuid::new_v4());
+
+        let mut requests = self.lock();
+        let now = Utc::now();
+        requests.retain(|_, request| request.expires > now);
+        requests.insert(
+            request_uri.clone(),
+            PushedRequest {
+                client_id: client_id.into(),
+                parameters,
+                expires: now + lifetime,
+            },
+        );
+
+        request_uri
+    }
+
+    /// Get a request which hasn't expired yet
+    pub fn get(&self, request_uri: &str) -> Option<PushedRequest> {
+        self.lock()
+            .get(request_uri)
+            .filter(|request| request.expires > Utc::now())
+            .cloned()
+    }
+
+    /// Remove a request, once it was used
+    pub fn remove(&self, request_uri: &str) -> Option<PushedRequest> {
+        self.lock().remove(request_uri)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, PushedRequest>> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// A login session of a user agent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The ID of the session, only known to the user agent
+    pub id: String,
+    /// The public session ID, for the `sid` claim
+    pub sid: String,
+    /// The authenticated user
+    pub subject: String,
+    /// When the user was authenticated
+    pub auth_time: DateTime<Utc>,
+    /// When the session ends, the user has to authenticate again
+    pub expires: DateTime<Utc>,
+    /// The clients which were authorized during the session
+    pub clients: BTreeSet<String>,
+}
+
+/// How long a login session lasts
+pub const SESSION_LIFETIME: Duration = Duration::hours(12);
+
+/// The login sessions of an issuer
+#[derive(Clone, Debug)]
+pub struct Sessions {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    lifetime: Duration,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new(SESSION_LIFETIME)
+    }
+}
+
+impl Sessions {
+    pub fn new(lifetime: Duration) -> Self {
+        Self {
+            sessions: Default::default(),
+            lifetime,
+        }
+    }
+
+    /// Authenticate a user, creating a new session
+    pub fn create(&self, subject: impl Into<String>) -> Session {
+        let now = Utc::now();
+        let session = Session {
+            id: uuid::Uuid::new_v4().to_string(),
+            sid: uuid::Uuid::new_v4().to_string(),
+            subject: subject.into(),
+            auth_time: now,
+            expires: now + self.lifetime,
+            clients: Default::default(),
+        };
+
+        let mut sessions = self.lock();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(session.id.clone(), session.clone());
+
+        session
+    }
+
+    /// Get a session, unless it expired
+    pub fn get(&self, id: &str) -> Option<Session> {
+        self.lock()
+            .get(id)
+            .filter(|session| session.expires > Utc::now())
+            .cloned()
+    }
+
+    /// Record that a client was authorized during a session
+    pub fn add_client(&self, id: &str, client_id: &str) {
+        if let Some(session) = self.lock().get_mut(id) {
+            session.clients.insert(client_id.to_string());
+        }
+    }
+
+    /// End a session
+    pub fn remove(&self, id: &str) -> Option<Session> {
+        self.lock().remove(id)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        // the map stays consistent, even if a holder of the lock panicked
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let sessions = Sessions::new(Duration::zero());
+        let expired = sessions.create("Marvin");
+        assert!(sessions.get(&expired.id).is_none());
+
+        // expired sessions are removed when creating new ones
+        sessions.create("Marvin");
+        assert_eq!(sessions.lock().len(), 1);
+
+        let sessions = Sessions::default();
+        let session = sessions.create("Marvin");
+        assert_eq!(sessions.get(&session.id), Some(session));
+    }
+}
@@ -1,34 +1,85 @@
-use crate::{extensions::ConnectionInformation, oidc::AccessTokenClaims, secrets::Key};
+use super::{AudiencePolicy, ClaimTarget, ClaimsPolicy, LifetimePolicy};
+use crate::{
+    extensions::{
+        Authentication, CertificateBinding, ConnectionInformation, DpopBinding, Resources,
+    },
+    oidc::{AccessTokenClaims, AdditionalClaims, Confirmation},
+    secrets::Key,
+};
 use anyhow::bail;
 use biscuit::{
-    ClaimsSet, CompactJson, CompactPart, RegisteredClaims, SingleOrMultiple, Timestamp,
+    ClaimsSet, CompactJson, CompactPart, Empty, RegisteredClaims, SingleOrMultiple, Timestamp,
     jws::{Compact, RegisteredHeader},
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use openidconnect::{
-    Audience, EmptyAdditionalClaims, IssuerUrl, StandardClaims, SubjectIdentifier,
-    core::CoreIdTokenClaims,
+    AccessToken, AccessTokenHash, Audience, AuthorizationCode, AuthorizationCodeHash, ClientId,
+    IdTokenClaims, IssuerUrl, Nonce, StandardClaims, SubjectIdentifier, core::CoreGenderClaim,
 };
 use oxide_auth::primitives::{generator::TagGrant, grant::Grant};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use url::Url;
+
+/// The audience used when nothing else is requested
+pub const DEFAULT_AUDIENCE: &str = "some-audience";
 
-const AUD: &str = "some-audience";
+/// The information a token is created from
+#[derive(Clone, Debug)]
+pub struct TokenInformation {
+    pub subject: String,
+    /// The client the token is issued to
+    pub client_id: Option<String>,
+    pub audience: Vec<String>,
+    pub scope: String,
+    pub expiry: DateTime<Utc>,
+    /// When the user was authenticated
+    pub auth_time: Option<DateTime<Utc>>,
+    /// Additional claims, added to the token as-is
+    pub additional: Map<String, Value>,
+    /// The key the token is bound to
+    pub confirmation: Option<Confirmation>,
+}
 
 pub struct JwtAccessGenerator {
     /// The relative base of the issuer
     issuer_base: String,
     key: Key,
+    lifetimes: LifetimePolicy,
+    audiences: AudiencePolicy,
+    claims: ClaimsPolicy,
 }
 
 impl JwtAccessGenerator {
     pub fn new(issuer_base: String, key: Key) -> Self {
-        Self { issuer_base, key }
+        Self {
+            issuer_base,
+            key,
+            lifetimes: Default::default(),
+            audiences: Default::default(),
+            claims: Default::default(),
+        }
     }
 
-    fn create(&self, grant: &Grant) -> Result<String, anyhow::Error> {
-        let expiry =
-            chrono::DateTime::from_timestamp(grant.until.timestamp(), 0).map(Timestamp::from);
+    /// Add the claims mapped for the users and clients
+    pub fn with_claims(mut self, claims: ClaimsPolicy) -> Self {
+        self.claims = claims;
+        self
+    }
 
+    /// Use the audiences configured for the clients and scopes
+    pub fn with_audiences(mut self, audiences: AudiencePolicy) -> Self {
+        self.audiences = audiences;
+        self
+    }
+
+    /// Use the lifetimes of the clients for the expiry of refresh tokens
+    pub fn with_lifetimes(mut self, lifetimes: LifetimePolicy) -> Self {
+        self.lifetimes = lifetimes;
+        self
+    }
+
+    fn create(&self, grant: &Grant, expiry: DateTime<Utc>) -> Result<String, anyhow::Error> {
         let Some(conn) = grant
             .extensions
             .private()
@@ -46,19 +97,65 @@ impl JwtAccessGenerator {
         };
 
         let issuer = format!("{}://{}{}", conn.scheme, conn.host, self.issuer_base);
+        let authentication = Authentication::from_grant(grant);
+        let audience = match Resources::from_grant(grant) {
+            resources if resources.is_empty() => {
+                self.audiences.audiences(&grant.client_id, &grant.scope)
+            }
+            resources => resources,
+        };
+        let confirmation = Confirmation {
+            jkt: DpopBinding::from_grant(grant),
+            x5t_s256: CertificateBinding::from_grant(grant),
+        };
+
+        self.mint(
+            &issuer,
+            &TokenInformation {
+                subject: grant.owner_id.clone(),
+                client_id: Some(grant.client_id.clone()),
+                audience,
+                scope: grant.scope.to_string(),
+                expiry,
+                auth_time: authentication.and_then(|auth| auth.auth_time()),
+                additional: self.claims.claims(
+                    ClaimTarget::AccessToken,
+                    &grant.owner_id,
+                    Some(&grant.client_id),
+                    Some(&grant.scope),
+                ),
+                confirmation: (confirmation != Confirmation::default()).then_some(confirmation),
+            },
+        )
+    }
+
+    /// Create an access token for an issuer, independent of a grant
+    pub fn mint(&self, issuer: &str, token: &TokenInformation) -> Result<String, anyhow::Error> {
+        let expiry = DateTime::from_timestamp(token.expiry.timestamp(), 0).map(Timestamp::from);
+
+        let audience = match token.audience.as_slice() {
+            [] => None,
+            [audience] => Some(SingleOrMultiple::Single(audience.clone())),
+            audience => Some(SingleOrMultiple::Multiple(audience.to_vec())),
+        };
 
         let expected_claims = ClaimsSet::<AccessTokenClaims> {
             registered: RegisteredClaims {
-                issuer: Some(issuer),
-                subject: Some(grant.owner_id.clone()),
+                issuer: Some(issuer.to_string()),
+                subject: Some(token.subject.clone()),
                 issued_at: Some(Utc::now().into()),
-                audience: Some(SingleOrMultiple::Single(AUD.to_string())),
+                audience,
                 expiry,
                 ..Default::default()
             },
             private: AccessTokenClaims {
-                azp: Some(grant.client_id.clone()),
-                scope: grant.scope.to_string(),
+                azp: token.client_id.clone(),
+                auth_time: token
+                    .auth_time
+                    .map(|auth_time| auth_time.timestamp() as u64),
+                scope: token.scope.clone(),
+                cnf: token.confirmation.clone(),
+                additional: token.additional.clone(),
                 ..Default::default()
             },
         };
@@ -67,10 +164,94 @@ impl JwtAccessGenerator {
     }
 }
 
+/// Lifetime of the JWT of an authorization response, which is consumed right away
+const RESPONSE_LIFETIME: Duration = Duration::minutes(10);
+
+/// Creates the JWT of a JWT secured authorization response (JARM)
+#[derive(Clone)]
+pub struct JwtResponseGenerator {
+    key: Key,
+    issuer: Url,
+    client_id: String,
+}
+
+impl std::fmt::Debug for JwtResponseGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtResponseGenerator")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtResponseGenerator {
+    pub fn new(key: Key, issuer: Url, client_id: impl Into<String>) -> Self {
+        Self {
+            key,
+            issuer,
+            client_id: client_id.into(),
+        }
+    }
+
+    /// Create a response JWT, carrying the parameters of the authorization response
+    pub fn create<'a>(
+        &self,
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<String, anyhow::Error> {
+        let claims = ClaimsSet::<Map<String, Value>> {
+            registered: RegisteredClaims {
+                issuer: Some(self.issuer.to_string()),
+                audience: Some(SingleOrMultiple::Single(self.client_id.clone())),
+                expiry: Some((Utc::now() + RESPONSE_LIFETIME).into()),
+                ..Default::default()
+            },
+            private: params
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.into()))
+                .collect(),
+        };
+
+        encode(&self.key, claims)
+    }
+}
+
+/// Lifetime of a logout token, which is sent to the client right away
+const LOGOUT_TOKEN_LIFETIME: Duration = Duration::minutes(2);
+
+/// The `typ` header of logout tokens, which keeps them from being mistaken for other JWTs
+const LOGOUT_TOKEN_TYPE: &str = "logout+jwt";
+
+/// The claims of a logout token, besides the registered ones
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LogoutTokenClaims {
+    events: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<String>,
+}
+
+impl CompactJson for LogoutTokenClaims {}
+
+/// Decode a token issued with the key, verifying the signature but not the claims
+pub fn verify(key: &Key, token: &str) -> Result<ClaimsSet<Map<String, Value>>, anyhow::Error> {
+    let token = Compact::<ClaimsSet<Map<String, Value>>, Empty>::new_encoded(token)
+        .into_decoded(&key.secret(), key.algorithm())?;
+    Ok(token.payload()?.clone())
+}
+
 fn encode<T: CompactPart>(key: &Key, claims: T) -> Result<String, anyhow::Error> {
+    encode_typed(key, None, claims)
+}
+
+/// Encode a JWT, explicitly typed by the `typ` header
+fn encode_typed<T: CompactPart>(
+    key: &Key,
+    media_type: Option<&str>,
+    claims: T,
+) -> Result<String, anyhow::Error> {
     let jwt = Compact::new_decoded(
         From::from(RegisteredHeader {
             algorithm: key.algorithm(),
+            media_type: media_type.map(ToString::to_string),
             key_id: Some(key.id().to_string()),
             ..Default::default()
         }),
@@ -81,20 +262,36 @@ fn encode<T: CompactPart>(key: &Key, claims: T) -> Result<String, anyhow::Error>
 }
 
 impl TagGrant for JwtAccessGenerator {
-    fn tag(&mut self, _usage: u64, grant: &Grant) -> Result<String, ()> {
-        self.create(grant).map_err(|err| {
+    fn tag(&mut self, usage: u64, grant: &Grant) -> Result<String, ()> {
+        // the token map tags access tokens with even, and refresh tokens with odd usages
+        let expiry = match usage % 2 {
+            0 => grant.until,
+            _ => Utc::now() + self.lifetimes.client(&grant.client_id).refresh_token(),
+        };
+
+        self.create(grant, expiry).map_err(|err| {
             tracing::warn!("Unable to create JWT: {err}");
         })
     }
 }
 
+/// Values binding an ID token to the authorization response it is part of
+#[derive(Clone, Debug, Default)]
+pub struct IdTokenBinding {
+    pub nonce: Option<String>,
+    /// The access token issued alongside, for the `at_hash` claim
+    pub access_token: Option<String>,
+    /// The authorization code issued alongside, for the `c_hash` claim
+    pub code: Option<String>,
+}
+
 pub struct JwtIdGenerator {
     issuer: IssuerUrl,
     key: Key,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct CoreIdToken(CoreIdTokenClaims);
+struct CoreIdToken(IdTokenClaims<AdditionalClaims, CoreGenderClaim>);
 
 impl CompactJson for CoreIdToken {}
 
@@ -103,21 +300,97 @@ impl JwtIdGenerator {
         Self { key, issuer }
     }
 
-    pub fn create(&self) -> Result<String, anyhow::Error> {
-        let aud = vec![Audience::new(AUD.into())];
+    pub fn create(&self, lifetime: Duration) -> Result<String, anyhow::Error> {
+        self.mint(&TokenInformation {
+            subject: "Marvin".into(),
+            client_id: None,
+            audience: vec![DEFAULT_AUDIENCE.to_string()],
+            scope: Default::default(),
+            expiry: Utc::now() + lifetime,
+            auth_time: None,
+            additional: Default::default(),
+            confirmation: None,
+        })
+    }
+
+    /// Create an ID token, independent of a grant
+    pub fn mint(&self, token: &TokenInformation) -> Result<String, anyhow::Error> {
+        self.mint_bound(token, &Default::default())
+    }
+
+    /// Create a logout token, for OpenID Connect Back-Channel Logout
+    pub fn logout_token(
+        &self,
+        client_id: &str,
+        subject: Option<&str>,
+        sid: Option<&str>,
+    ) -> Result<String, anyhow::Error> {
+        let now = Utc::now();
+        let claims = ClaimsSet::<LogoutTokenClaims> {
+            registered: RegisteredClaims {
+                issuer: Some(self.issuer.as_str().to_string()),
+                subject: subject.map(ToString::to_string),
+                audience: Some(SingleOrMultiple::Single(client_id.to_string())),
+                issued_at: Some(now.into()),
+                expiry: Some((now + LOGOUT_TOKEN_LIFETIME).into()),
+                id: Some(uuid::Uuid::new_v4().to_string()),
+                ..Default::default()
+            },
+            private: LogoutTokenClaims {
+                events: Map::from_iter([(
+                    "http://schemas.openid.net/event/backchannel-logout".to_string(),
+                    Value::Object(Default::default()),
+                )]),
+                sid: sid.map(ToString::to_string),
+            },
+        };
+
+        encode_typed(&self.key, Some(LOGOUT_TOKEN_TYPE), claims)
+    }
+
+    /// Create an ID token, bound to an authorization response
+    pub fn mint_bound(
+        &self,
+        token: &TokenInformation,
+        binding: &IdTokenBinding,
+    ) -> Result<String, anyhow::Error> {
+        let aud = token.audience.iter().cloned().map(Audience::new).collect();
         let issue_time = Utc::now();
-        let expiration_time = Utc::now() + Duration::seconds(600);
-        let subject = SubjectIdentifier::new("Marvin".into());
+        let subject = SubjectIdentifier::new(token.subject.clone());
         let std = StandardClaims::new(subject);
 
-        let claims = CoreIdTokenClaims::new(
+        let claims = IdTokenClaims::new(
             self.issuer.clone(),
             aud,
-            expiration_time,
+            token.expiry,
             issue_time,
             std,
-            EmptyAdditionalClaims::default(),
-        );
+            AdditionalClaims {
+                claims: token.additional.clone(),
+            },
+        )
+        .set_authorized_party(token.client_id.clone().map(ClientId::new))
+        .set_auth_time(token.auth_time)
+        .set_nonce(binding.nonce.clone().map(Nonce::new));
+
+        let alg = self.key.core_alg();
+        let jwk = self.key.key();
+        let claims = match &binding.access_token {
+            Some(access_token) => claims.set_access_token_hash(Some(AccessTokenHash::from_token(
+                &AccessToken::new(access_token.clone()),
+                &alg,
+                &jwk,
+            )?)),
+            None => claims,
+        };
+        let claims = match &binding.code {
+            Some(code) => claims.set_code_hash(Some(AuthorizationCodeHash::from_code(
+                &AuthorizationCode::new(code.clone()),
+                &alg,
+                &jwk,
+            )?)),
+            None => claims,
+        };
 
         encode(&self.key, CoreIdToken(claims))
     }
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// A user of an issuer
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    /// The ID of the user, used as subject
+    pub id: String,
+    /// Attributes of the user, which claims can be mapped from
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+    /// The groups the user is a member of
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Roles of the user, per client
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<String>>,
+}
+
+impl User {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            attributes: Default::default(),
+            groups: Default::default(),
+            roles: Default::default(),
+        }
+    }
+}
+
+/// The users an issuer has when none are configured
+pub fn default_users() -> Vec<User> {
+    vec![User::new("Marvin")]
+}
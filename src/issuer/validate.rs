@@ -0,0 +1,389 @@
+use super::{
+    ClaimMapping, ClaimValue, Client, Issuer, TlsClientAuth, is_reserved_claim, is_valid_resource,
+    transitive_groups,
+};
+use oxide_auth::primitives::{registrar::RegisteredUrl, scope::Scope};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Formatter,
+};
+
+/// Minimum length of the HMAC secret, in bytes
+///
+/// HS256 requires a key of at least the size of the hash output.
+const MIN_SECRET_LEN: usize = 32;
+
+/// A problem found when validating the configuration
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// Location of the problem, as a path into the configuration
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl Issuer {
+    /// Run semantic checks on the issuer configuration
+    ///
+    /// This reports all problems found, rather than stopping at the first one. The location of
+    /// each problem is relative to the provided `location` of the issuer.
+    pub fn validate(&self, location: &str) -> Vec<Problem> {
+        let mut problems = vec![];
+        let mut report =
+            |location: String, message: String| problems.push(Problem { location, message });
+
+        if self.key.0.len() < MIN_SECRET_LEN {
+            report(
+                format!("{location}.key"),
+                format!(
+                    "HMAC secret is too weak, must be at least {MIN_SECRET_LEN} bytes (has {})",
+                    self.key.0.len()
+                ),
+            );
+        }
+
+        let mut scopes = HashSet::new();
+        for (i, scope) in self.scopes.iter().enumerate() {
+            match scope.parse::<Scope>() {
+                Ok(_) if scope.contains(' ') => report(
+                    format!("{location}.scopes.{i}"),
+                    format!("scope must not contain whitespace: '{scope}'"),
+                ),
+                Ok(_) => {
+                    scopes.insert(scope.as_str());
+                }
+                Err(err) => report(
+                    format!("{location}.scopes.{i}"),
+                    format!("invalid scope '{scope}': {err}"),
+                ),
+            }
+        }
+
+        for name in self.scope_descriptions.keys() {
+            if !scopes.contains(name.as_str()) {
+                report(
+                    format!("{location}.scopeDescriptions.{name}"),
+                    format!("description of unknown scope '{name}'"),
+                );
+            }
+        }
+
+        for name in self.scope_audiences.keys() {
+            if !scopes.contains(name.as_str()) {
+                report(
+                    format!("{location}.scopeAudiences.{name}"),
+                    format!("audiences of unknown scope '{name}'"),
+                );
+            }
+        }
+
+        validate_claims(
+            self,
+            &self.claims,
+            &scopes,
+            &format!("{location}.claims"),
+            &mut report,
+        );
+
+        let mut ids = HashSet::new();
+        for (i, client) in self.clients.iter().enumerate() {
+            let location = format!("{location}.clients.{i}");
+
+            if !ids.insert(client.id()) {
+                report(
+                    location.clone(),
+                    format!("duplicate client id: {}", client.id()),
+                );
+            }
+
+            let default_scope = match client {
+                Client::Confidential { default_scope, .. } => default_scope,
+                Client::Public { default_scope, .. } => default_scope,
+            };
+
+            match default_scope.parse::<Scope>() {
+                Ok(default_scope) => {
+                    for scope in default_scope.iter() {
+                        if !scopes.contains(scope) {
+                            report(
+                                format!("{location}.defaultScope"),
+                                format!("default scope '{scope}' is not a scope of the issuer"),
+                            );
+                        }
+                    }
+                }
+                Err(err) => report(
+                    format!("{location}.defaultScope"),
+                    format!("invalid default scope: {err}"),
+                ),
+            }
+
+            match (&client.settings().tls_client_auth, client) {
+                (Some(_), Client::Public { .. }) => report(
+                    format!("{location}.tlsClientAuth"),
+                    "only confidential clients can authenticate using TLS".into(),
+                ),
+                (Some(TlsClientAuth::SelfSigned), _)
+                    if !client.settings().jwks.as_ref().is_some_and(|jwks| {
+                        jwks.keys
+                            .iter()
+                            .any(|key| key.common.x509_chain.is_some())
+                    }) =>
+                {
+                    report(
+                        format!("{location}.tlsClientAuth"),
+                        "self-signed TLS client authentication requires keys with certificates (x5c)"
+                            .into(),
+                    )
+                }
+                _ => {}
+            }
+
+            validate_claims(
+                self,
+                &client.settings().claims,
+                &scopes,
+                &format!("{location}.claims"),
+                &mut report,
+            );
+
+            for (j, resource) in client.settings().resources.iter().enumerate() {
+                if !is_valid_resource(resource) {
+                    report(
+                        format!("{location}.resources.{j}"),
+                        format!(
+                            "resource must be an absolute URI without a fragment: '{resource}'"
+                        ),
+                    );
+                }
+            }
+
+            if let Client::Public { redirect_urls, .. } = client {
+                if redirect_urls.is_empty() {
+                    report(
+                        format!("{location}.redirectUrls"),
+                        "public client requires at least one redirect URL".into(),
+                    );
+                }
+                for (j, url) in redirect_urls.iter().enumerate() {
+                    if let Err(err) = RegisteredUrl::try_from(url.clone()) {
+                        report(
+                            format!("{location}.redirectUrls.{j}"),
+                            format!("invalid redirect URL: {err}"),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            let location = format!("{location}.groups.{i}");
+
+            if !ids.insert(group.id.as_str()) {
+                report(
+                    location.clone(),
+                    format!("duplicate group id: {}", group.id),
+                );
+            }
+            validate_groups(self, &group.groups, &location, &mut report);
+            if transitive_groups(group.groups.iter().map(String::as_str), &self.groups)
+                .contains(&group.id.as_str())
+            {
+                report(
+                    format!("{location}.groups"),
+                    format!("group '{}' is a member of itself", group.id),
+                );
+            }
+            validate_roles(self, &group.roles, &location, &mut report);
+        }
+
+        let mut ids = HashSet::new();
+        for (i, user) in self.users.iter().enumerate() {
+            let location = format!("{location}.users.{i}");
+
+            if !ids.insert(user.id.as_str()) {
+                report(location.clone(), format!("duplicate user id: {}", user.id));
+            }
+            validate_groups(self, &user.groups, &location, &mut report);
+            validate_roles(self, &user.roles, &location, &mut report);
+        }
+
+        problems
+    }
+}
+
+fn validate_claims(
+    issuer: &Issuer,
+    claims: &[ClaimMapping],
+    scopes: &HashSet<&str>,
+    location: &str,
+    report: &mut impl FnMut(String, String),
+) {
+    for (i, claim) in claims.iter().enumerate() {
+        if claim.name.split('.').any(str::is_empty) {
+            report(
+                format!("{location}.{i}.name"),
+                format!("invalid claim name: '{}'", claim.name),
+            );
+        } else if is_reserved_claim(&claim.name) {
+            report(
+                format!("{location}.{i}.name"),
+                format!("claim '{}' is set by the issuer", claim.name),
+            );
+        }
+        if let Some(scope) = &claim.scope {
+            if !scopes.contains(scope.as_str()) {
+                report(
+                    format!("{location}.{i}.scope"),
+                    format!("claim depends on unknown scope '{scope}'"),
+                );
+            }
+        }
+        if let ClaimValue::ClientRoles(client_id) = &claim.value {
+            if !issuer.clients.iter().any(|client| client.id() == client_id) {
+                report(
+                    format!("{location}.{i}.value"),
+                    format!("roles of unknown client '{client_id}'"),
+                );
+            }
+        }
+    }
+}
+
+/// Check that the groups a user or group is a member of exist
+fn validate_groups(
+    issuer: &Issuer,
+    groups: &[String],
+    location: &str,
+    report: &mut impl FnMut(String, String),
+) {
+    for (i, id) in groups.iter().enumerate() {
+        if !issuer.groups.iter().any(|group| &group.id == id) {
+            report(
+                format!("{location}.groups.{i}"),
+                format!("unknown group '{id}'"),
+            );
+        }
+    }
+}
+
+/// Check that assigned roles are roles of their client
+fn validate_roles(
+    issuer: &Issuer,
+    roles: &BTreeMap<String, Vec<String>>,
+    location: &str,
+    report: &mut impl FnMut(String, String),
+) {
+    for (client_id, roles) in roles {
+        let location = format!("{location}.roles.{client_id}");
+        match issuer
+            .clients
+            .iter()
+            .find(|client| client.id() == client_id)
+        {
+            None => report(location, format!("roles of unknown client '{client_id}'")),
+            Some(client) => {
+                for (i, role) in roles.iter().enumerate() {
+                    if !client.settings().roles.contains(role) {
+                        report(
+                            format!("{location}.{i}"),
+                            format!("'{role}' is not a role of client '{client_id}'"),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::issuer::{ClientSettings, RedirectUrl};
+
+    #[test]
+    fn validate_problems() -> anyhow::Result<()> {
+        let mut issuer = Issuer::new("too-short", ["openid", "foo"])?
+            .add_client(Client::Confidential {
+                id: "client".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid bar".into(),
+                settings: Default::default(),
+            })
+            .add_client(Client::Public {
+                id: "client".into(),
+                redirect_urls: vec![RedirectUrl::Exact {
+                    url: "not a url".into(),
+                    ignore_localhost_port: false,
+                }],
+                default_scope: "openid".into(),
+                settings: Default::default(),
+            })
+            .add_client(Client::Confidential {
+                id: "mtls".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    tls_client_auth: Some(TlsClientAuth::SelfSigned),
+                    resources: vec!["https://api.example.com".into(), "api".into()],
+                    roles: vec!["admin".into()],
+                    ..Default::default()
+                },
+            });
+        issuer
+            .scope_audiences
+            .insert("bar".into(), vec!["bar-api".into()]);
+        issuer.claims = serde_json::from_value(serde_json::json!([
+            {"name": "realm_access.roles", "value": {"attribute": "roles"}},
+            {"name": "realm_access.", "value": {"attribute": "roles"}},
+            {"name": "sub", "value": {"static": "someone-else"}},
+            {"name": "roles", "value": {"clientRoles": "unknown"}, "scope": "roles"},
+            {"name": "cnf.jkt", "value": {"static": "forged"}},
+        ]))?;
+        issuer.groups = serde_json::from_value(serde_json::json!([
+            {"id": "admins", "groups": ["staff"], "roles": {"mtls": ["admin", "root"]}},
+            {"id": "staff", "groups": ["admins", "unknown"]},
+        ]))?;
+        issuer.users = serde_json::from_value(serde_json::json!([
+            {"id": "Marvin", "groups": ["admins", "nobody"], "roles": {"unknown": ["admin"]}},
+        ]))?;
+
+        let problems = issuer
+            .validate("issuers.test")
+            .into_iter()
+            .map(|problem| problem.location)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            problems,
+            vec![
+                "issuers.test.key",
+                "issuers.test.scopeAudiences.bar",
+                "issuers.test.claims.1.name",
+                "issuers.test.claims.2.name",
+                "issuers.test.claims.3.scope",
+                "issuers.test.claims.3.value",
+                "issuers.test.claims.4.name",
+                "issuers.test.clients.0.defaultScope",
+                "issuers.test.clients.1",
+                "issuers.test.clients.1.redirectUrls.0",
+                "issuers.test.clients.2.tlsClientAuth",
+                "issuers.test.clients.2.resources.1",
+                "issuers.test.groups.0.groups",
+                "issuers.test.groups.0.roles.mtls.1",
+                "issuers.test.groups.1.groups.1",
+                "issuers.test.groups.1.groups",
+                "issuers.test.users.0.groups.1",
+                "issuers.test.users.0.roles.unknown",
+            ]
+        );
+
+        Ok(())
+    }
+}
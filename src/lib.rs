@@ -2,7 +2,9 @@ mod extensions;
 mod oidc;
 mod secrets;
 
+pub mod audit;
 pub mod config;
 pub mod endpoints;
 pub mod issuer;
+pub mod metrics;
 pub mod server;
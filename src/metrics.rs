@@ -0,0 +1,134 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::time::Instant;
+
+/// Label of clients which aren't registered with the issuer
+pub const UNKNOWN_CLIENT: &str = "unknown";
+
+/// Metrics of an application
+///
+/// Each application has its own registry, so that running more than one instance in the same
+/// process doesn't mix up the numbers.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Tokens issued, by issuer, client and grant type
+    pub tokens_issued: IntCounterVec,
+    /// Authorization requests, by issuer and client
+    pub authorization_requests: IntCounterVec,
+    /// Failed client authentications, by issuer and client
+    pub client_authentication_failures: IntCounterVec,
+    /// Token endpoint errors, by issuer and OAuth error code
+    pub token_errors: IntCounterVec,
+    /// Request latencies, by route, method and status
+    pub request_duration: HistogramVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("garage_door".into()), None)
+            // only fails for an invalid prefix, which is static
+            .expect("valid registry");
+
+        // metric definitions are static, so registering them can only fail due to a bug
+        fn register<T: prometheus::core::Collector + Clone + 'static>(
+            registry: &Registry,
+            metric: Result<T, prometheus::Error>,
+        ) -> T {
+            let metric = metric.expect("valid metric");
+            registry
+                .register(Box::new(metric.clone()))
+                .expect("unique metric");
+            metric
+        }
+
+        Self {
+            tokens_issued: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("tokens_issued_total", "Number of tokens issued"),
+                    &["issuer", "client", "grant_type"],
+                ),
+            ),
+            authorization_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "authorization_requests_total",
+                        "Number of authorization requests",
+                    ),
+                    &["issuer", "client"],
+                ),
+            ),
+            client_authentication_failures: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "client_authentication_failures_total",
+                        "Number of failed client authentications",
+                    ),
+                    &["issuer", "client"],
+                ),
+            ),
+            token_errors: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("token_errors_total", "Number of token endpoint errors"),
+                    &["issuer", "error"],
+                ),
+            ),
+            request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "request_duration_seconds",
+                        "Duration of HTTP requests, in seconds",
+                    ),
+                    &["route", "method", "status"],
+                ),
+            ),
+            registry,
+        }
+    }
+
+    /// Encode the metrics in the Prometheus text format
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    /// Record the latency of a request, to be used with [`actix_web::middleware::from_fn`]
+    pub async fn track<B: MessageBody>(
+        &self,
+        req: ServiceRequest,
+        next: Next<B>,
+    ) -> Result<ServiceResponse<B>, actix_web::Error> {
+        let start = Instant::now();
+        let method = req.method().to_string();
+
+        let resp = next.call(req).await?;
+
+        let route = resp
+            .request()
+            .match_pattern()
+            .unwrap_or_else(|| "<unmatched>".into());
+        self.request_duration
+            .with_label_values(&[&route, &method, resp.status().as_str()])
+            .observe(start.elapsed().as_secs_f64());
+
+        Ok(resp)
+    }
+}
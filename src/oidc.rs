@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 /// Access token claims
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -17,4 +18,32 @@ pub struct AccessTokenClaims {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub email_verified: Option<bool>,
+
+    /// The key the token is bound to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+
+    /// Any additional claims
+    #[serde(flatten)]
+    pub additional: Map<String, Value>,
+}
+
+/// Confirmation of the key a token is bound to, RFC 7800
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Confirmation {
+    /// Thumbprint of the DPoP key, RFC 9449
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jkt: Option<String>,
+    /// Thumbprint of the TLS client certificate, RFC 8705
+    #[serde(default, rename = "x5t#S256", skip_serializing_if = "Option::is_none")]
+    pub x5t_s256: Option<String>,
 }
+
+/// Additional, non-standard, ID token claims
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AdditionalClaims {
+    #[serde(flatten)]
+    pub claims: Map<String, Value>,
+}
+
+impl openidconnect::AdditionalClaims for AdditionalClaims {}
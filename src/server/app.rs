@@ -1,6 +1,8 @@
 use crate::{
+    audit::AuditLog,
     endpoints,
     issuer::{IssueBuildError, Issuer},
+    metrics::Metrics,
     server::state::ApplicationState,
 };
 use actix_web::{web, web::ServiceConfig};
@@ -9,7 +11,7 @@ use url::Url;
 
 #[derive(Clone)]
 pub struct Application {
-    state: web::Data<ApplicationState>,
+    state: ApplicationState,
 }
 
 impl Application {
@@ -19,22 +21,47 @@ impl Application {
         issuers: HashMap<String, Issuer>,
     ) -> Result<Self, IssueBuildError> {
         let state = ApplicationState::new(issuers, base, path)?;
-        let state = web::Data::new(state);
 
         Ok(Self { state })
     }
 
+    /// The metrics of the application
+    pub fn metrics(&self) -> Metrics {
+        self.state.metrics().clone()
+    }
+
+    /// Set the audit log, events are dropped by default
+    pub fn audit(&mut self, audit: AuditLog) -> &mut Self {
+        self.state.set_audit(audit);
+        self
+    }
+
     pub fn configure(&self, svc: &mut ServiceConfig) {
-        svc.app_data(self.state.clone())
+        svc.app_data(web::Data::new(self.state.clone()))
+            // must be registered before the issuer endpoints, which would otherwise match
+            .service(endpoints::health::live)
+            .service(endpoints::health::ready)
+            .service(endpoints::health::version)
+            .service(endpoints::metrics::metrics)
+            .service(endpoints::well_known::authorization_server)
+            .service(endpoints::well_known::openid_configuration)
+            .service(endpoints::well_known::webfinger)
             .service(endpoints::index)
             .service(endpoints::issuer::index)
             .service(endpoints::issuer::discovery)
             .service(endpoints::issuer::auth_get)
+            .service(endpoints::issuer::pushed_authorization_request)
+            .service(endpoints::issuer::consent_decision)
+            .service(endpoints::issuer::list_consents)
+            .service(endpoints::issuer::revoke_consent)
             .service(endpoints::issuer::keys)
             .service(endpoints::issuer::token)
             .service(endpoints::issuer::userinfo_get)
             .service(endpoints::issuer::userinfo_post)
-            .service(endpoints::issuer::logout)
+            .service(endpoints::issuer::revoke)
+            .service(endpoints::issuer::logout_get)
+            .service(endpoints::issuer::logout_post)
+            .service(endpoints::issuer::check_session)
             .service(endpoints::issuer::refresh);
     }
 }
@@ -1,11 +1,21 @@
 pub mod app;
 pub mod state;
+pub mod tls;
 
-use crate::{issuer::IssueBuildError, issuer::Issuer, server::app::Application};
+use crate::{
+    audit::AuditLog,
+    issuer::IssueBuildError,
+    issuer::Issuer,
+    server::{
+        app::Application,
+        tls::{TlsConfig, TlsError},
+    },
+};
 use actix_cors::Cors;
 use actix_web::{
     App, HttpServer,
-    middleware::{Logger, NormalizePath},
+    body::BoxBody,
+    middleware::{Logger, Next, NormalizePath, from_fn},
     web,
 };
 use std::{
@@ -26,6 +36,8 @@ pub enum StartupError {
     Url(#[from] url::ParseError),
     #[error(transparent)]
     Issue(#[from] IssueBuildError),
+    #[error(transparent)]
+    Tls(#[from] TlsError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -42,8 +54,11 @@ pub struct Server {
     announce_url: Option<Box<dyn FnOnce(Url) + Send + Sync + 'static>>,
 
     issuers: HashMap<String, Issuer>,
+    audit: AuditLog,
 
     workers: Option<usize>,
+
+    tls: Option<TlsConfig>,
 }
 
 impl Default for Server {
@@ -60,7 +75,9 @@ impl Server {
             issuers: Default::default(),
             base: None,
             announce_url: None,
+            audit: AuditLog::disabled(),
             workers: None,
+            tls: None,
         }
     }
 
@@ -84,6 +101,17 @@ impl Server {
         self
     }
 
+    /// Serve using TLS, accepting client certificates
+    pub fn tls(&mut self, tls: TlsConfig) -> &mut Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn audit(&mut self, audit: AuditLog) -> &mut Self {
+        self.audit = audit;
+        self
+    }
+
     pub fn add_issuer(&mut self, name: String, issuer: Issuer) -> Result<&mut Self, Error> {
         match self.issuers.entry(name.clone()) {
             Entry::Vacant(entry) => {
@@ -107,7 +135,12 @@ impl Server {
         let listener = listener.into_std()?;
 
         let addr = listener.local_addr()?;
-        let public_base = Url::parse(&format!("http://{addr}"))?;
+        let tls = self.tls.take().map(TlsConfig::build).transpose()?;
+        let scheme = match tls {
+            Some(_) => "https",
+            None => "http",
+        };
+        let public_base = Url::parse(&format!("{scheme}://{addr}"))?;
 
         let announce_base = if let Some(path) = &self.base {
             public_base.join(path)?
@@ -116,10 +149,17 @@ impl Server {
         };
         log::info!("Listening on: {announce_base}");
 
-        let app = Application::new(public_base, self.base.clone(), self.issuers)?;
+        let mut app = Application::new(public_base, self.base.clone(), self.issuers)?;
+        app.audit(self.audit);
+        let metrics = app.metrics();
 
         let mut http = HttpServer::new(move || {
+            let metrics = metrics.clone();
             App::new()
+                .wrap(from_fn(move |req, next: Next<BoxBody>| {
+                    let metrics = metrics.clone();
+                    async move { metrics.track(req, next).await }
+                }))
                 .wrap(Cors::permissive())
                 .wrap(NormalizePath::trim())
                 .wrap(Logger::default())
@@ -130,8 +170,14 @@ impl Server {
                     }
                     None => app.configure(svc),
                 })
-        })
-        .listen(listener)?;
+        });
+
+        http = match tls {
+            Some((config, trust)) => http
+                .on_connect(move |connection, data| trust.on_connect(connection, data))
+                .listen_rustls_0_23(listener, config)?,
+            None => http.listen(listener)?,
+        };
 
         if let Some(workers) = self.workers {
             http = http.workers(workers);
@@ -144,3 +190,254 @@ impl Server {
         Ok(http.run())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::issuer::{CertificateSubject, Client, ClientSettings, RedirectUrl, TlsClientAuth};
+    use base64::{
+        Engine,
+        prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    };
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use reqwest::{Certificate, Identity, StatusCode};
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use std::{
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+    };
+
+    fn client_params(name: &str) -> anyhow::Result<CertificateParams> {
+        let mut params = CertificateParams::new(vec![])?;
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        Ok(params)
+    }
+
+    #[actix_web::test]
+    async fn mutual_tls() -> anyhow::Result<()> {
+        let ca_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec![])?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Test CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key)?;
+
+        let server_key = KeyPair::generate()?;
+        let server_cert = CertificateParams::new(vec!["127.0.0.1".to_string()])?.signed_by(
+            &server_key,
+            &ca,
+            &ca_key,
+        )?;
+
+        let pki_key = KeyPair::generate()?;
+        let pki_cert = client_params("client")?.signed_by(&pki_key, &ca, &ca_key)?;
+
+        let self_signed_key = KeyPair::generate()?;
+        let self_signed_cert = client_params("self-signed")?.self_signed(&self_signed_key)?;
+        let point = self_signed_key.public_key_raw();
+        let jwks = serde_json::from_value(serde_json::json!({"keys": [{
+            "kty": "EC",
+            "crv": "P-256",
+            "x": BASE64_URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": BASE64_URL_SAFE_NO_PAD.encode(&point[33..]),
+            "x5c": [BASE64_STANDARD.encode(self_signed_cert.der())],
+        }]}))?;
+
+        let issuer = Issuer::new("this-is-not-a-secret-test-key-0123456789", ["openid"])?
+            .add_client(Client::Confidential {
+                id: "pki".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    tls_client_auth: Some(TlsClientAuth::Pki(CertificateSubject::SubjectDn(
+                        "CN=client".into(),
+                    ))),
+                    tls_client_certificate_bound_access_tokens: true,
+                    ..Default::default()
+                },
+            })
+            .add_client(Client::Confidential {
+                id: "self-signed".into(),
+                secret: "secret".to_string().into(),
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    tls_client_auth: Some(TlsClientAuth::SelfSigned),
+                    jwks: Some(jwks),
+                    ..Default::default()
+                },
+            })
+            .add_client(Client::Public {
+                id: "native".into(),
+                redirect_urls: vec![RedirectUrl::Semantic("http://localhost/cb".parse()?)],
+                default_scope: "openid".into(),
+                settings: ClientSettings {
+                    tls_client_certificate_bound_access_tokens: true,
+                    ..Default::default()
+                },
+            });
+
+        let tls = TlsConfig::from_pem(
+            server_cert.pem().as_bytes(),
+            server_key.serialize_pem().as_bytes(),
+        )?
+        .client_ca_pem(ca.pem().as_bytes())?;
+
+        let url = Arc::new(Mutex::new(None));
+        let mut server = Server::new();
+        server
+            .port(0)
+            .bind(Ipv4Addr::LOCALHOST.into())
+            .tls(tls)
+            .add_issuer("test".into(), issuer)?;
+        server.announce_url({
+            let url = url.clone();
+            move |announced| *url.lock().unwrap() = Some(announced)
+        });
+        let server = server.create().await?;
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        let base = url.lock().unwrap().take().unwrap();
+        assert_eq!(base.scheme(), "https");
+
+        let http = |identity: Option<(&rcgen::Certificate, &KeyPair)>| {
+            let mut builder = reqwest::Client::builder()
+                .use_rustls_tls()
+                .redirect(reqwest::redirect::Policy::none())
+                .add_root_certificate(Certificate::from_pem(ca.pem().as_bytes())?);
+            if let Some((cert, key)) = identity {
+                builder = builder.identity(Identity::from_pem(
+                    format!("{}{}", cert.pem(), key.serialize_pem()).as_bytes(),
+                )?);
+            }
+            Ok::<_, anyhow::Error>(builder.build()?)
+        };
+        let pki = http(Some((&pki_cert, &pki_key)))?;
+        let self_signed = http(Some((&self_signed_cert, &self_signed_key)))?;
+        let anonymous = http(None)?;
+
+        let token_url = base.join("test/token")?;
+        let userinfo_url = base.join("test/userinfo")?;
+        let token = |client: &reqwest::Client, params: &[(&str, &str)]| {
+            client
+                .post(token_url.clone())
+                .form(&[params, &[("grant_type", "client_credentials")]].concat())
+                .send()
+        };
+
+        // authenticated by a certificate of the trusted CA, binding the token to it
+        let resp: Value = token(&pki, &[("client_id", "pki")]).await?.json().await?;
+        let access_token = resp["access_token"].as_str().unwrap_or_default();
+        let payload = access_token.split('.').nth(1).unwrap_or_default();
+        let claims: Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(payload)?)?;
+        assert_eq!(
+            claims["cnf"]["x5t#S256"],
+            BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(pki_cert.der()))
+        );
+
+        let userinfo = |client: &reqwest::Client| {
+            client
+                .get(userinfo_url.clone())
+                .bearer_auth(access_token)
+                .send()
+        };
+        assert_eq!(userinfo(&pki).await?.status(), StatusCode::OK);
+        assert_eq!(
+            userinfo(&anonymous).await?.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            userinfo(&self_signed).await?.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // the secret can't be used, and neither can an untrusted certificate
+        for (client, params) in [
+            (
+                &pki,
+                &[("client_id", "pki"), ("client_secret", "secret")][..],
+            ),
+            (&anonymous, &[("client_id", "pki")]),
+            (&self_signed, &[("client_id", "pki")]),
+        ] {
+            let resp: Value = token(client, params).await?.json().await?;
+            assert_eq!(resp["error"], "invalid_client");
+        }
+
+        // authenticated by a registered self-signed certificate
+        let resp: Value = token(&self_signed, &[("client_id", "self-signed")])
+            .await?
+            .json()
+            .await?;
+        let unbound = resp["access_token"].as_str().unwrap_or_default();
+
+        // a token which isn't bound to a certificate can be used without one
+        let resp = anonymous
+            .get(userinfo_url.clone())
+            .bearer_auth(unbound)
+            .send()
+            .await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp: Value = token(&pki, &[("client_id", "self-signed")])
+            .await?
+            .json()
+            .await?;
+        assert_eq!(resp["error"], "invalid_client");
+
+        // refresh tokens are bound to the certificate as well
+        let resp = pki
+            .get(base.join("test/auth")?)
+            .query(&[
+                ("client_id", "native"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("scope", "openid"),
+                ("response_type", "code"),
+            ])
+            .send()
+            .await?;
+        let location: Url = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .unwrap()
+            .to_str()?
+            .parse()?;
+        let (_, code) = location.query_pairs().find(|(k, _)| k == "code").unwrap();
+        let resp: Value = pki
+            .post(token_url.clone())
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("client_id", "native"),
+                ("redirect_uri", "http://localhost/cb"),
+                ("code", &code),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+        let refresh_token = resp["refresh_token"].as_str().unwrap_or_default();
+        let refresh = |client: &reqwest::Client| {
+            client
+                .post(token_url.clone())
+                .form(&[
+                    ("grant_type", "refresh_token"),
+                    ("client_id", "native"),
+                    ("refresh_token", refresh_token),
+                ])
+                .send()
+        };
+        let resp: Value = refresh(&anonymous).await?.json().await?;
+        assert_eq!(resp["error"], "invalid_request");
+        let resp: Value = refresh(&self_signed).await?.json().await?;
+        assert_eq!(resp["error"], "invalid_grant");
+        let resp: Value = refresh(&pki).await?.json().await?;
+        assert!(resp["access_token"].is_string());
+
+        handle.stop(true).await;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
-use crate::issuer::{IssueBuildError, Issuer, IssuerState};
+use crate::{
+    audit::AuditLog,
+    issuer::{IssueBuildError, Issuer, IssuerState},
+    metrics::Metrics,
+};
 use actix_web::dev::ConnectionInfo;
 use log::Level::Info;
 use std::collections::HashMap;
@@ -8,6 +12,7 @@ use url::Url;
 #[derive(Clone)]
 pub struct ApplicationState {
     base_path: Option<String>,
+    audit: AuditLog,
     inner: Arc<InnerApplicationState>,
 }
 
@@ -34,6 +39,8 @@ impl ApplicationState {
         }
 
         let inner = InnerApplicationState {
+            metrics: Metrics::new(),
+            http: reqwest::Client::new(),
             issuers: issuers
                 .into_iter()
                 .map(|(name, issuer)| {
@@ -49,6 +56,7 @@ impl ApplicationState {
         Ok(Self {
             inner: Arc::new(inner),
             base_path,
+            audit: AuditLog::disabled(),
         })
     }
 
@@ -62,6 +70,38 @@ impl ApplicationState {
         self.inner.issuers.keys().cloned().collect()
     }
 
+    /// Get the metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+
+    /// Get the HTTP client, for calling out to clients
+    pub fn http(&self) -> &reqwest::Client {
+        &self.inner.http
+    }
+
+    /// Get the audit log
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub(crate) fn set_audit(&mut self, audit: AuditLog) {
+        self.audit = audit;
+    }
+
+    /// Check if requests can be served as expected
+    ///
+    /// The issuers are built and have their keys loaded at startup, but the audit log may stop
+    /// writing events later on.
+    pub fn is_ready(&self) -> bool {
+        self.audit.is_running()
+            && self
+                .inner
+                .issuers
+                .values()
+                .all(|issuer| matches!(issuer.keys(), Ok(keys) if !keys.keys().is_empty()))
+    }
+
     /// Build the base URL based on the request
     pub fn build_base(&self, conn: &ConnectionInfo) -> Result<Url, url::ParseError> {
         let url = format!("{}://{}", conn.scheme(), conn.host());
@@ -79,4 +119,6 @@ impl ApplicationState {
 
 struct InnerApplicationState {
     issuers: HashMap<String, IssuerState>,
+    metrics: Metrics,
+    http: reqwest::Client,
 }
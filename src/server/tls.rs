@@ -0,0 +1,192 @@
+//! TLS listener, accepting client certificates for mutual TLS (RFC 8705)
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::{dev::Extensions, rt::net::TcpStream};
+use rustls::{
+    DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme,
+    client::danger::HandshakeSignatureValid,
+    crypto::{CryptoProvider, ring},
+    pki_types::{CertificateDer, PrivateKeyDer, UnixTime},
+    server::{
+        WebPkiClientVerifier,
+        danger::{ClientCertVerified, ClientCertVerifier},
+    },
+};
+use std::{any::Any, io, sync::Arc};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read PEM data: {0}")]
+    Pem(#[from] io::Error),
+    #[error("missing certificate")]
+    MissingCertificate,
+    #[error("missing private key")]
+    MissingKey,
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error(transparent)]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+/// The certificate and key of the server, and the CAs trusted to issue client certificates
+pub struct TlsConfig {
+    certificates: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    client_ca: Vec<CertificateDer<'static>>,
+}
+
+impl TlsConfig {
+    /// Create a new configuration from a PEM encoded certificate chain and private key
+    pub fn from_pem(certificate: &[u8], key: &[u8]) -> Result<Self, TlsError> {
+        let certificates =
+            rustls_pemfile::certs(&mut &*certificate).collect::<Result<Vec<_>, _>>()?;
+        if certificates.is_empty() {
+            return Err(TlsError::MissingCertificate);
+        }
+        let key = rustls_pemfile::private_key(&mut &*key)?.ok_or(TlsError::MissingKey)?;
+
+        Ok(Self {
+            certificates,
+            key,
+            client_ca: vec![],
+        })
+    }
+
+    /// Trust the PEM encoded CA certificates to issue client certificates
+    ///
+    /// Clients using `tls_client_auth` must present a certificate issued by one of them.
+    pub fn client_ca_pem(mut self, ca: &[u8]) -> Result<Self, TlsError> {
+        for certificate in rustls_pemfile::certs(&mut &*ca) {
+            self.client_ca.push(certificate?);
+        }
+        Ok(self)
+    }
+
+    /// Create the server configuration, and the verifier for the trust of client certificates
+    pub(crate) fn build(self) -> Result<(ServerConfig, ClientTrust), TlsError> {
+        let provider = Arc::new(ring::default_provider());
+
+        let trust = if self.client_ca.is_empty() {
+            None
+        } else {
+            let mut roots = RootCertStore::empty();
+            for certificate in self.client_ca {
+                roots.add(certificate)?;
+            }
+            Some(
+                WebPkiClientVerifier::builder_with_provider(roots.into(), provider.clone())
+                    .build()?,
+            )
+        };
+
+        let config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(Arc::new(AnyClientCertificate { provider }))
+            .with_single_cert(self.certificates, self.key)?;
+
+        Ok((config, ClientTrust(trust)))
+    }
+}
+
+/// The certificate a client presented when connecting, available as connection data
+#[derive(Clone, Debug)]
+pub struct ClientCertificate {
+    /// The DER encoded certificate
+    pub certificate: CertificateDer<'static>,
+    /// If the certificate was issued by one of the trusted CAs
+    pub trusted: bool,
+}
+
+/// Decides if a client certificate was issued by a trusted CA
+#[derive(Clone)]
+pub(crate) struct ClientTrust(Option<Arc<dyn ClientCertVerifier>>);
+
+impl ClientTrust {
+    /// Record the certificate of a connection, to be used as connection data
+    pub fn on_connect(&self, connection: &dyn Any, data: &mut Extensions) {
+        let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+            return;
+        };
+        let Some((certificate, intermediates)) = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|chain| chain.split_first())
+        else {
+            return;
+        };
+
+        let trusted = self.0.as_ref().is_some_and(|verifier| {
+            verifier
+                .verify_client_cert(certificate, intermediates, UnixTime::now())
+                .is_ok()
+        });
+
+        data.insert(ClientCertificate {
+            certificate: certificate.clone().into_owned(),
+            trusted,
+        });
+    }
+}
+
+/// Accepts any client certificate, if it can be trusted is decided per client
+///
+/// Self-signed certificates must be accepted too, so the handshake can't reject any certificate.
+/// Only the proof of possession of the private key is checked.
+#[derive(Debug)]
+struct AnyClientCertificate {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ClientCertVerifier for AnyClientCertificate {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}